@@ -0,0 +1,228 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use glutin::{ElementState, VirtualKeyCode};
+
+use event::Input;
+use sys;
+use world::PlayerId;
+
+
+const DEFAULT_BINDINGS: &'static str = include_str!("../bindings.cfg");
+/// Axis positions below this are treated as zero.
+#[cfg(feature = "gamepad")]
+const DEAD_ZONE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Thrust,
+    Turn,
+    Shoot,
+    Pause,
+    Glow,
+    Debug,
+}
+
+impl Action {
+    fn make_input(&self, player: PlayerId, value: f32) -> Option<Input> {
+        Some(match *self {
+            Action::Thrust => Input::Control(sys::control::Event::EvThrust(player, value)),
+            Action::Turn => Input::Control(sys::control::Event::EvTurn(player, value)),
+            Action::Shoot => Input::Bullet(sys::bullet::Event::EvShoot(player, value != 0.0)),
+            // pausing is toggled on press only
+            Action::Pause if value != 0.0 => Input::Pause,
+            Action::Pause => return None,
+            Action::Glow if value != 0.0 => Input::Glow,
+            Action::Glow => return None,
+            Action::Debug if value != 0.0 => Input::Debug,
+            Action::Debug => return None,
+        })
+    }
+
+    fn describe(&self, value: f32) -> &'static str {
+        match *self {
+            Action::Thrust => "thrust",
+            Action::Turn if value < 0.0 => "turn left",
+            Action::Turn => "turn right",
+            Action::Shoot => "shoot",
+            Action::Pause => "pause",
+            Action::Glow => "toggle glow",
+            Action::Debug => "toggle debug shapes",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    Key(VirtualKeyCode),
+    /// Gamepad button, named after `gilrs::Button`.
+    Button(String),
+    /// Gamepad axis, named after `gilrs::Axis`.
+    Axis(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub trigger: Trigger,
+    pub action: Action,
+    pub value: f32,
+    pub player: PlayerId,
+}
+
+macro_rules! parse_key {
+    ($name:expr, $($key:ident),*) => {
+        match $name {
+            $( stringify!($key) => Some(VirtualKeyCode::$key), )*
+            _ => None,
+        }
+    }
+}
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    parse_key!(name,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Left, Up, Right, Down, Space, Return, Back, Tab,
+        LShift, RShift, LControl, RControl, LAlt, RAlt,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+        Numpad5, Numpad6, Numpad7, Numpad8, Numpad9)
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Malformed binding: {}", line))
+}
+
+/// The table translating keys and gamepad input into game events.
+pub struct Bindings {
+    list: Vec<Binding>,
+}
+
+impl Bindings {
+    pub fn parse(text: &str) -> io::Result<Bindings> {
+        let mut list = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let words: Vec<_> = line.split_whitespace().collect();
+            let (kind, name, action, rest) = match words.as_slice() {
+                &[kind, name, "=", action, ref rest @ ..] => (kind, name, action, rest),
+                _ => return Err(invalid_data(line)),
+            };
+            let (value, player) = match rest {
+                &[] => (None, None),
+                &["player", player] => (None, Some(player)),
+                &[value] => (Some(value), None),
+                &[value, "player", player] => (Some(value), Some(player)),
+                _ => return Err(invalid_data(line)),
+            };
+            let trigger = match kind {
+                "key" => Trigger::Key(try!(parse_key(name).ok_or_else(|| invalid_data(line)))),
+                "button" => Trigger::Button(name.to_string()),
+                "axis" => Trigger::Axis(name.to_string()),
+                _ => return Err(invalid_data(line)),
+            };
+            let action = match action {
+                "thrust" => Action::Thrust,
+                "turn" => Action::Turn,
+                "shoot" => Action::Shoot,
+                "pause" => Action::Pause,
+                "glow" => Action::Glow,
+                "debug" => Action::Debug,
+                _ => return Err(invalid_data(line)),
+            };
+            let value = match value {
+                Some(v) => try!(v.parse().map_err(|_| invalid_data(line))),
+                None => 1.0,
+            };
+            // players are counted from one in the bindings
+            let player = match player {
+                Some(p) => match p.parse::<PlayerId>() {
+                    Ok(p) if p > 0 => p - 1,
+                    _ => return Err(invalid_data(line)),
+                },
+                None => 0,
+            };
+            list.push(Binding {
+                trigger: trigger,
+                action: action,
+                value: value,
+                player: player,
+            });
+        }
+        Ok(Bindings {
+            list: list,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Bindings> {
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+        Bindings::parse(&text)
+    }
+
+    pub fn default() -> Bindings {
+        Bindings::parse(DEFAULT_BINDINGS).unwrap()
+    }
+
+    /// Human-readable list of the controls.
+    pub fn describe(&self) -> String {
+        let mut out = String::new();
+        for b in self.list.iter() {
+            let _ = match b.trigger {
+                Trigger::Key(key) => write!(out, "    {:?} - {}",
+                    key, b.action.describe(b.value)),
+                Trigger::Button(ref name) => write!(out, "    Gamepad {} - {}",
+                    name, b.action.describe(b.value)),
+                Trigger::Axis(ref name) => write!(out, "    Gamepad {} axis - {}",
+                    name, b.action.describe(b.value)),
+            };
+            let _ = if b.player != 0 {
+                writeln!(out, " (player {})", b.player + 1)
+            } else {
+                writeln!(out, "")
+            };
+        }
+        out
+    }
+
+    pub fn on_key(&self, state: ElementState, key: VirtualKeyCode) -> Vec<Input> {
+        self.list.iter()
+            .filter(|b| b.trigger == Trigger::Key(key))
+            .filter_map(|b| b.action.make_input(b.player, match state {
+                ElementState::Pressed => b.value,
+                ElementState::Released => 0.0,
+            }))
+            .collect()
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn on_button(&self, name: &str, pressed: bool) -> Vec<Input> {
+        self.list.iter()
+            .filter(|b| match b.trigger {
+                Trigger::Button(ref n) => n == name,
+                _ => false,
+            })
+            .filter_map(|b| b.action.make_input(b.player, if pressed { b.value } else { 0.0 }))
+            .collect()
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn on_axis(&self, name: &str, position: f32) -> Vec<Input> {
+        let position = if position.abs() < DEAD_ZONE { 0.0 } else { position };
+        self.list.iter()
+            .filter(|b| match b.trigger {
+                Trigger::Axis(ref n) => n == name,
+                _ => false,
+            })
+            .filter_map(|b| b.action.make_input(b.player, match b.action {
+                // thrust can only go forward
+                Action::Thrust => (position * b.value).max(0.0),
+                _ => position * b.value,
+            }))
+            .collect()
+    }
+}
@@ -0,0 +1,68 @@
+use gl;
+use image::Image;
+
+
+/// Decides which of the frames shown in the window are saved,
+/// either on request or periodically.
+pub struct Capture {
+    period: Option<u64>,
+    frame: u64,
+    shot_requested: bool,
+}
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture {
+            period: None,
+            frame: 0,
+            shot_requested: false,
+        }
+    }
+
+    /// Save every N-th frame, e.g. for making videos.
+    pub fn every(mut self, period: u64) -> Capture {
+        assert!(period > 0);
+        self.period = Some(period);
+        self
+    }
+
+    /// Save the next frame.
+    pub fn request_shot(&mut self) {
+        self.shot_requested = true;
+    }
+
+    /// Advance to the next frame, returning the file path if it needs to be saved.
+    pub fn next_frame(&mut self) -> Option<String> {
+        let frame = self.frame;
+        self.frame += 1;
+        if self.shot_requested {
+            self.shot_requested = false;
+            Some(format!("screenshot-{}.png", frame))
+        } else {
+            match self.period {
+                Some(period) if frame % period == 0 => Some(format!("frame-{:06}.png", frame / period)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Read the back buffer of the window, before it's swapped.
+pub fn read_frame(gl: &gl::Gl, size: [u32; 2]) -> Image {
+    let mut data = vec![0u8; 4 * (size[0] * size[1]) as usize];
+    unsafe {
+        gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl.ReadBuffer(gl::BACK);
+        gl.ReadPixels(0, 0, size[0] as gl::types::GLsizei, size[1] as gl::types::GLsizei,
+            gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut _);
+    }
+    // the rows go from the bottom in OpenGL,
+    // and the window is opaque, so the alpha is not kept
+    let pixels = data.chunks(4 * size[0] as usize).rev()
+        .flat_map(|row| row.chunks(4).map(|c| [c[0], c[1], c[2], 0xFF]))
+        .collect();
+    Image {
+        size: size,
+        pixels: pixels,
+    }
+}
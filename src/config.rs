@@ -0,0 +1,322 @@
+use std::{fmt, io};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rustc_serialize::json;
+
+use sys::aster::SHAPE_RADIUS;
+use sys::physics::CELL_SIZE;
+
+
+const DEFAULT_CONFIG: &'static str = include_str!("../config.json");
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Ship {
+    pub lives: u8,
+    pub thrust_speed: f32,
+    pub turn_speed: f32,
+    pub radius: f32,
+    pub health: u16,
+    pub damage: u16,
+    pub respawn_time: f32,
+    pub invulnerable_time: f32,
+    /// Exhaust particles per second while thrusting.
+    pub exhaust_rate: f32,
+    pub exhaust_speed: f32,
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Bullet {
+    pub speed: f32,
+    pub cool_time: f32,
+    pub life_time: f32,
+    pub radius: f32,
+    pub health: u16,
+    pub damage: u16,
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct AsteroidKind {
+    pub health: u16,
+    /// Size of the drawn outline, which the collision follows.
+    pub scale: f32,
+    pub speed: f32,
+    pub score: u32,
+    /// Number of pieces of the next kind it breaks into.
+    pub fragments: u8,
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Asteroid {
+    /// Seconds between spawning new asteroids.
+    pub spawn_rate: f32,
+    /// Spawning time accumulated at the start, to have some asteroids right away.
+    pub head_start: f32,
+    /// Maximum angular velocity.
+    pub spin: f32,
+    pub damage: u16,
+    /// Asteroids never leave the wrapped space, so their number is limited.
+    pub max_wrapped: usize,
+    /// Number of large asteroids spawned in each wave.
+    pub wave_size: u32,
+    /// Asteroid kinds, from the largest to the smallest.
+    pub kinds: Vec<AsteroidKind>,
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Particle {
+    pub life_time: f32,
+    /// Number of pieces flying out of a destroyed object.
+    pub debris_count: u8,
+    pub debris_speed: f32,
+}
+
+/// Color animations of the entities.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Tint {
+    /// How long a damaged object stays highlighted.
+    pub flash_time: f32,
+    /// Time for the invulnerable ships to go off and on again.
+    pub blink_period: f32,
+    /// How long a destroyed object takes to disappear.
+    pub fade_time: f32,
+}
+
+/// The vector display look.
+#[derive(Clone, Copy, Debug, RustcDecodable)]
+pub struct Glow {
+    /// How much of the blurred picture is added on top.
+    pub strength: f32,
+    /// Share of the brightness kept from the previous frame.
+    pub persistence: f32,
+    /// Darkening of every other line.
+    pub scanlines: f32,
+}
+
+/// Sprite sheet replacing one of the placeholder shapes.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Sprite {
+    /// Path of the PNG image.
+    pub path: String,
+    /// Half-size of the quad, the placeholder shapes being about 0.5 across.
+    pub extents: [f32; 2],
+    /// Number of the frame columns and rows in the image.
+    pub columns: u32,
+    pub rows: u32,
+    /// Frames per second.
+    pub rate: f32,
+    /// Start over after the last frame, instead of staying on it.
+    pub repeat: bool,
+    /// Filter the texture bilinearly instead of keeping the pixels sharp.
+    pub smooth: bool,
+}
+
+/// Optional sprites of the objects, the shapes are drawn where missing.
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Sprites {
+    pub ship: Option<Sprite>,
+    pub bullet: Option<Sprite>,
+    pub particle: Option<Sprite>,
+    pub asteroid: Option<Sprite>,
+}
+
+#[derive(Clone, Debug, RustcDecodable)]
+pub struct Config {
+    pub ship: Ship,
+    pub bullet: Bullet,
+    pub asteroid: Asteroid,
+    pub particle: Particle,
+    pub tint: Tint,
+    pub glow: Glow,
+    pub sprites: Sprites,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(json::DecoderError),
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "unable to read the config: {}", e),
+            Error::Parse(ref e) => write!(f, "unable to parse the config: {}", e),
+            Error::Invalid(ref what) => write!(f, "invalid config value: {}", what),
+        }
+    }
+}
+
+fn check(condition: bool, what: String) -> Result<(), Error> {
+    if condition { Ok(()) } else { Err(Error::Invalid(what)) }
+}
+
+// the collision grid can only handle objects fitting into a cell
+fn check_radius(radius: f32, what: &str) -> Result<(), Error> {
+    check(radius > 0.0 && radius <= 0.5 * CELL_SIZE,
+        format!("{}.radius = {} is out of (0, {}]", what, radius, 0.5 * CELL_SIZE))
+}
+
+fn check_positive(value: f32, what: &str) -> Result<(), Error> {
+    check(value > 0.0, format!("{} = {} must be positive", what, value))
+}
+
+fn check_non_negative(value: f32, what: &str) -> Result<(), Error> {
+    check(value >= 0.0, format!("{} = {} must not be negative", what, value))
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        let config: Config = try!(json::decode(text).map_err(Error::Parse));
+        try!(config.validate());
+        Ok(config)
+    }
+
+    /// Read the text of a config file, to be parsed later.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+        let mut text = String::new();
+        try!(File::open(path).and_then(|mut f| f.read_to_string(&mut text)).map_err(Error::Io));
+        Ok(text)
+    }
+
+    pub fn default() -> Config {
+        Config::parse(DEFAULT_CONFIG).unwrap()
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let ship = &self.ship;
+        try!(check(ship.lives > 0, "ship.lives must be positive".to_string()));
+        try!(check_non_negative(ship.thrust_speed, "ship.thrust_speed"));
+        try!(check_radius(ship.radius, "ship"));
+        try!(check(ship.health > 0, "ship.health must be positive".to_string()));
+        try!(check_non_negative(ship.respawn_time, "ship.respawn_time"));
+        try!(check_non_negative(ship.invulnerable_time, "ship.invulnerable_time"));
+        try!(check_non_negative(ship.exhaust_rate, "ship.exhaust_rate"));
+        try!(check_non_negative(ship.exhaust_speed, "ship.exhaust_speed"));
+
+        let bullet = &self.bullet;
+        try!(check_non_negative(bullet.speed, "bullet.speed"));
+        try!(check_non_negative(bullet.cool_time, "bullet.cool_time"));
+        try!(check_positive(bullet.life_time, "bullet.life_time"));
+        try!(check_radius(bullet.radius, "bullet"));
+        try!(check(bullet.health > 0, "bullet.health must be positive".to_string()));
+
+        let aster = &self.asteroid;
+        try!(check_positive(aster.spawn_rate, "asteroid.spawn_rate"));
+        try!(check_non_negative(aster.head_start, "asteroid.head_start"));
+        try!(check_non_negative(aster.spin, "asteroid.spin"));
+        try!(check(aster.wave_size > 0, "asteroid.wave_size must be positive".to_string()));
+        try!(check(!aster.kinds.is_empty(), "asteroid.kinds must not be empty".to_string()));
+        // the kinds are counted by a byte
+        try!(check(aster.kinds.len() <= 0x100,
+            format!("asteroid.kinds has {} entries, no more than 256 are allowed", aster.kinds.len())));
+        for (i, kind) in aster.kinds.iter().enumerate() {
+            let name = format!("asteroid.kinds[{}]", i);
+            try!(check(kind.health > 0, format!("{}.health must be positive", name)));
+            // the outline reaches as far as the shape radius at scale one
+            let max_scale = 0.5 * CELL_SIZE / SHAPE_RADIUS;
+            try!(check(kind.scale > 0.0 && kind.scale <= max_scale,
+                format!("{}.scale = {} is out of (0, {}]", name, kind.scale, max_scale)));
+            try!(check_positive(kind.speed, &format!("{}.speed", name)));
+            try!(check(kind.fragments == 0 || i + 1 < aster.kinds.len(),
+                format!("{}.fragments = {}, but there is no smaller kind to break into",
+                    name, kind.fragments)));
+        }
+
+        let particle = &self.particle;
+        try!(check_positive(particle.life_time, "particle.life_time"));
+        try!(check_non_negative(particle.debris_speed, "particle.debris_speed"));
+
+        let tint = &self.tint;
+        try!(check_non_negative(tint.flash_time, "tint.flash_time"));
+        try!(check_positive(tint.blink_period, "tint.blink_period"));
+        try!(check_non_negative(tint.fade_time, "tint.fade_time"));
+
+        let glow = &self.glow;
+        try!(check_non_negative(glow.strength, "glow.strength"));
+        try!(check(glow.persistence >= 0.0 && glow.persistence < 1.0,
+            format!("glow.persistence = {} is out of [0, 1)", glow.persistence)));
+        try!(check(glow.scanlines >= 0.0 && glow.scanlines <= 1.0,
+            format!("glow.scanlines = {} is out of [0, 1]", glow.scanlines)));
+
+        let sprites = &self.sprites;
+        for &(sprite, name) in [(&sprites.ship, "ship"), (&sprites.bullet, "bullet"),
+                                (&sprites.particle, "particle"), (&sprites.asteroid, "asteroid")].iter() {
+            if let Some(ref sprite) = *sprite {
+                let name = format!("sprites.{}", name);
+                try!(check_positive(sprite.extents[0], &format!("{}.extents[0]", name)));
+                try!(check_positive(sprite.extents[1], &format!("{}.extents[1]", name)));
+                try!(check(sprite.columns > 0 && sprite.rows > 0,
+                    format!("{} must have at least one column and one row", name)));
+                try!(check_non_negative(sprite.rate, &format!("{}.rate", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Error, DEFAULT_CONFIG};
+
+    // change one value of the default config, expecting it to be rejected
+    fn check_invalid(from: &str, to: &str, what: &str) {
+        assert_eq!(DEFAULT_CONFIG.matches(from).count(), 1, "{} is not unique", from);
+        match Config::parse(&DEFAULT_CONFIG.replace(from, to)) {
+            Err(Error::Invalid(ref message)) =>
+                assert!(message.contains(what), "{} doesn't mention {}", message, what),
+            other => panic!("{} is accepted: {:?}", to, other),
+        }
+    }
+
+    #[test]
+    fn default() {
+        Config::parse(DEFAULT_CONFIG).unwrap();
+    }
+
+    #[test]
+    fn malformed() {
+        match Config::parse("{ \"ship\": ") {
+            Err(Error::Parse(_)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn radius_too_large() {
+        check_invalid("\"radius\": 0.2", "\"radius\": 0.8", "ship.radius");
+    }
+
+    #[test]
+    fn scale_too_large() {
+        check_invalid("\"scale\": 1.0", "\"scale\": 3.0", "asteroid.kinds[0].scale");
+    }
+
+    #[test]
+    fn fragments_of_last_kind() {
+        check_invalid("\"fragments\": 0", "\"fragments\": 2", "asteroid.kinds[2].fragments");
+    }
+
+    #[test]
+    fn negative() {
+        check_invalid("\"spin\": 2.0", "\"spin\": -1.0", "asteroid.spin");
+    }
+
+    #[test]
+    fn not_positive() {
+        check_invalid("\"life_time\": 1.0", "\"life_time\": 0.0", "bullet.life_time");
+    }
+
+    #[test]
+    fn persistence_of_one() {
+        check_invalid("\"persistence\": 0.6", "\"persistence\": 1.0", "glow.persistence");
+    }
+
+    #[test]
+    fn sprite_without_frames() {
+        check_invalid("\"sprites\": {}", "\"sprites\": { \"ship\": { \"path\": \"ship.png\", \
+            \"extents\": [0.5, 0.5], \"columns\": 0, \"rows\": 1, \"rate\": 10.0, \
+            \"repeat\": true, \"smooth\": true } }", "sprites.ship");
+    }
+}
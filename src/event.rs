@@ -1,55 +1,430 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
 use std::sync::mpsc;
 use glutin;
+#[cfg(feature = "gamepad")]
+use gilrs;
+use binding::Bindings;
 use sys;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Control(sys::control::Event),
+    Bullet(sys::bullet::Event),
+    /// Stop or resume the game.
+    Pause,
+    /// Switch the vector display look on or off.
+    Glow,
+    /// Show or hide the debug shapes.
+    Debug,
+    /// The window got a new size, in pixels.
+    Resize(u32, u32),
+}
+
 pub struct ReceiverHub {
     pub control: mpsc::Receiver<sys::control::Event>,
     pub bullet: mpsc::Receiver<sys::bullet::Event>,
+    pub pause: mpsc::Receiver<()>,
+    pub glow: mpsc::Receiver<()>,
+    pub debug: mpsc::Receiver<()>,
+    pub resize: mpsc::Receiver<(u32, u32)>,
+}
+
+impl ReceiverHub {
+    /// Collect all the pending events.
+    pub fn drain(&self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        while let Ok(ev) = self.control.try_recv() {
+            inputs.push(Input::Control(ev));
+        }
+        while let Ok(ev) = self.bullet.try_recv() {
+            inputs.push(Input::Bullet(ev));
+        }
+        while let Ok(()) = self.pause.try_recv() {
+            inputs.push(Input::Pause);
+        }
+        while let Ok(()) = self.glow.try_recv() {
+            inputs.push(Input::Glow);
+        }
+        while let Ok(()) = self.debug.try_recv() {
+            inputs.push(Input::Debug);
+        }
+        while let Ok((w, h)) = self.resize.try_recv() {
+            inputs.push(Input::Resize(w, h));
+        }
+        inputs
+    }
 }
 
 pub struct SenderHub {
     control: mpsc::Sender<sys::control::Event>,
     bullet: mpsc::Sender<sys::bullet::Event>,
+    pause: mpsc::Sender<()>,
+    glow: mpsc::Sender<()>,
+    debug: mpsc::Sender<()>,
+    resize: mpsc::Sender<(u32, u32)>,
+    /// Keys that are down, to tell the repeated presses apart.
+    held: HashSet<glutin::VirtualKeyCode>,
 }
 
 impl SenderHub {
     pub fn new() -> (SenderHub, ReceiverHub) {
         let (sc, rc) = mpsc::channel();
         let (sb, rb) = mpsc::channel();
+        let (sp, rp) = mpsc::channel();
+        let (sg, rg) = mpsc::channel();
+        let (sd, rd) = mpsc::channel();
+        let (sr, rr) = mpsc::channel();
         (SenderHub {
             control: sc,
             bullet: sb,
+            pause: sp,
+            glow: sg,
+            debug: sd,
+            resize: sr,
+            held: HashSet::new(),
         },
         ReceiverHub {
             control: rc,
             bullet: rb,
+            pause: rp,
+            glow: rg,
+            debug: rd,
+            resize: rr,
         })
     }
 
-    pub fn process_glutin(&self, event: glutin::Event) {
-        use sys::control::Event::*;
-        use sys::bullet::Event::*;
-        use glutin::Event::KeyboardInput;
-        use glutin::{ElementState, VirtualKeyCode};
+    pub fn send(&self, input: Input) {
+        match input {
+            Input::Control(ev) => self.control.send(ev).unwrap(),
+            Input::Bullet(ev) => self.bullet.send(ev).unwrap(),
+            Input::Pause => self.pause.send(()).unwrap(),
+            Input::Glow => self.glow.send(()).unwrap(),
+            Input::Debug => self.debug.send(()).unwrap(),
+            Input::Resize(w, h) => self.resize.send((w, h)).unwrap(),
+        }
+    }
+
+    pub fn process_glutin(&mut self, event: glutin::Event, bindings: &Bindings) {
         match event {
-            KeyboardInput(state, _, Some(VirtualKeyCode::A)) =>
-                self.control.send(EvThrust(match state {
-                    ElementState::Pressed => 1.0,
-                    ElementState::Released => 0.0,
-                })).unwrap(),
-            KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Left)) =>
-                self.control.send(EvTurn(-1.0)).unwrap(),
-            KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Right)) =>
-                self.control.send(EvTurn(1.0)).unwrap(),
-            KeyboardInput(ElementState::Released, _, Some(k))
-                if k == VirtualKeyCode::Left || k == VirtualKeyCode::Right =>
-                self.control.send(EvTurn(0.0)).unwrap(),
-            KeyboardInput(state, _, Some(VirtualKeyCode::S)) =>
-                self.bullet.send(EvShoot(match state {
-                    ElementState::Pressed => true,
-                    ElementState::Released => false,
-                })).unwrap(),
+            glutin::Event::KeyboardInput(state, _, Some(key)) => {
+                // a held key keeps sending presses, which would flip the toggles
+                let changed = match state {
+                    glutin::ElementState::Pressed => self.held.insert(key),
+                    glutin::ElementState::Released => self.held.remove(&key),
+                };
+                if !changed {
+                    return
+                }
+                for input in bindings.on_key(state, key) {
+                    self.send(input);
+                }
+            },
+            // the releases go elsewhere while the window is not focused
+            glutin::Event::Focused(false) => self.held.clear(),
             _ => (),
         }
     }
+
+    #[cfg(feature = "gamepad")]
+    pub fn process_gilrs(&self, event: gilrs::Event, bindings: &Bindings) {
+        use gilrs::Event::*;
+        let inputs = match event {
+            ButtonPressed(button, _) => bindings.on_button(&format!("{:?}", button), true),
+            ButtonReleased(button, _) => bindings.on_button(&format!("{:?}", button), false),
+            AxisChanged(axis, position, _) => bindings.on_axis(&format!("{:?}", axis), position),
+            _ => Vec::new(),
+        };
+        for input in inputs {
+            self.send(input);
+        }
+    }
+}
+
+
+/// --- Replays ---
+
+// A replay is a text file, starting with the seed line and the options
+// of the game, followed by one line per frame time step or input event,
+// where the events specify the player index before the value:
+//     seed 12345
+//     players 2
+//     level 1.5
+//     wrap
+//     friendly-fire
+//     config {"ship": {"lives": 3, ...}, ...}
+//     0 dt 0.016
+//     0 thrust 0 1
+//     1 dt 0.017
+//     1 turn 0 -1
+//     1 shoot 1 1
+//     2 pause
+//     2 glow
+//     2 debug
+//     3 resize 800 600
+
+pub type Frame = u64;
+
+/// Game options that change the simulation. The replays keep them,
+/// since the recorded session goes differently without them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub players: usize,
+    pub wrap: bool,
+    pub level_scale: f32,
+    pub friendly_fire: bool,
+    /// Text of the config file, when not using the default one.
+    pub config: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            players: 1,
+            wrap: false,
+            level_scale: 1.0,
+            friendly_fire: false,
+            config: None,
+        }
+    }
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Malformed replay line: {}", line))
+}
+
+/// Writes the input stream of a session into a file.
+pub struct Recorder {
+    output: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(path: P, seed: usize, options: &Options)
+                                  -> io::Result<Recorder> {
+        let mut output = BufWriter::new(try!(File::create(path)));
+        try!(writeln!(output, "seed {}", seed));
+        try!(writeln!(output, "players {}", options.players));
+        try!(writeln!(output, "level {}", options.level_scale));
+        if options.wrap {
+            try!(writeln!(output, "wrap"));
+        }
+        if options.friendly_fire {
+            try!(writeln!(output, "friendly-fire"));
+        }
+        if let Some(ref config) = options.config {
+            // JSON doesn't need the line breaks
+            let line: Vec<_> = config.lines().map(|l| l.trim()).collect();
+            try!(writeln!(output, "config {}", line.join(" ")));
+        }
+        Ok(Recorder {
+            output: output,
+        })
+    }
+
+    pub fn record_frame(&mut self, frame: Frame, delta: sys::Delta, inputs: &[Input])
+                        -> io::Result<()> {
+        use sys::control::Event::*;
+        use sys::bullet::Event::*;
+        try!(writeln!(self.output, "{} dt {}", frame, delta));
+        for input in inputs {
+            try!(match *input {
+                Input::Control(EvThrust(p, v)) =>
+                    writeln!(self.output, "{} thrust {} {}", frame, p, v),
+                Input::Control(EvTurn(p, v)) =>
+                    writeln!(self.output, "{} turn {} {}", frame, p, v),
+                Input::Bullet(EvShoot(p, v)) =>
+                    writeln!(self.output, "{} shoot {} {}", frame, p, v as u8),
+                Input::Pause =>
+                    writeln!(self.output, "{} pause", frame),
+                Input::Glow =>
+                    writeln!(self.output, "{} glow", frame),
+                Input::Debug =>
+                    writeln!(self.output, "{} debug", frame),
+                Input::Resize(w, h) =>
+                    writeln!(self.output, "{} resize {} {}", frame, w, h),
+            });
+        }
+        // the game may end at any moment, don't keep anything in the buffer
+        self.output.flush()
+    }
+}
+
+/// The contents of a recorded session.
+pub struct Replay {
+    pub seed: usize,
+    pub options: Options,
+    pub deltas: Vec<sys::Delta>,
+    pub inputs: Vec<(Frame, Input)>,
+}
+
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        use sys::control::Event::*;
+        use sys::bullet::Event::*;
+        let mut seed = None;
+        let mut replay = Replay {
+            seed: 0,
+            options: Options::default(),
+            deltas: Vec::new(),
+            inputs: Vec::new(),
+        };
+        let file = BufReader::new(try!(File::open(path)));
+        for line in file.lines() {
+            let line = try!(line);
+            if line.starts_with("config ") {
+                replay.options.config = Some(line["config ".len() ..].to_string());
+                continue
+            }
+            let words: Vec<_> = line.split_whitespace().collect();
+            match words.as_slice() {
+                &[] => (),
+                &["seed", value] => {
+                    seed = Some(try!(value.parse().map_err(|_| invalid_data(&line))));
+                },
+                &["players", value] => {
+                    replay.options.players = try!(value.parse().ok()
+                        .filter(|&n| n > 0).ok_or_else(|| invalid_data(&line)));
+                },
+                &["level", value] => {
+                    replay.options.level_scale = try!(value.parse().ok()
+                        .filter(|&s| s >= 1.0).ok_or_else(|| invalid_data(&line)));
+                },
+                &["wrap"] => {
+                    replay.options.wrap = true;
+                },
+                &["friendly-fire"] => {
+                    replay.options.friendly_fire = true;
+                },
+                &[frame, "dt", value] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    if frame != replay.deltas.len() as Frame {
+                        return Err(invalid_data(&line))
+                    }
+                    replay.deltas.push(try!(value.parse().map_err(|_| invalid_data(&line))));
+                },
+                &[frame, "pause"] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    replay.inputs.push((frame, Input::Pause));
+                },
+                &[frame, "glow"] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    replay.inputs.push((frame, Input::Glow));
+                },
+                &[frame, "debug"] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    replay.inputs.push((frame, Input::Debug));
+                },
+                &[frame, "resize", width, height] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    let width = try!(width.parse().map_err(|_| invalid_data(&line)));
+                    let height = try!(height.parse().map_err(|_| invalid_data(&line)));
+                    replay.inputs.push((frame, Input::Resize(width, height)));
+                },
+                &[frame, kind, player, value] => {
+                    let frame: Frame = try!(frame.parse().map_err(|_| invalid_data(&line)));
+                    let player = try!(player.parse().map_err(|_| invalid_data(&line)));
+                    let value: f32 = try!(value.parse().map_err(|_| invalid_data(&line)));
+                    let input = match kind {
+                        "thrust" => Input::Control(EvThrust(player, value)),
+                        "turn" => Input::Control(EvTurn(player, value)),
+                        "shoot" => Input::Bullet(EvShoot(player, value != 0.0)),
+                        _ => return Err(invalid_data(&line)),
+                    };
+                    replay.inputs.push((frame, input));
+                },
+                _ => return Err(invalid_data(&line)),
+            }
+        }
+        // without the seed, the session can't be reproduced
+        replay.seed = try!(seed.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+            "The replay has no seed line")));
+        Ok(replay)
+    }
+}
+
+/// Plays back the recorded input events, frame by frame.
+pub struct Player {
+    inputs: Vec<(Frame, Input)>,
+    position: usize,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Player {
+        Player {
+            inputs: replay.inputs,
+            position: 0,
+        }
+    }
+
+    /// Get the events that were recorded on the given frame.
+    pub fn fetch(&mut self, frame: Frame) -> Vec<Input> {
+        let start = self.position;
+        while self.position < self.inputs.len() && self.inputs[self.position].0 <= frame {
+            self.position += 1;
+        }
+        self.inputs[start .. self.position].iter().map(|&(_, input)| input).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::{ErrorKind, Write};
+    use sys::bullet::Event::*;
+    use sys::control::Event::*;
+    use super::{Input, Options, Player, Recorder, Replay};
+
+    #[test]
+    fn record_and_load() {
+        let path = env::temp_dir().join("yasteroids-record-and-load.txt");
+        let options = Options {
+            players: 2,
+            wrap: true,
+            level_scale: 1.5,
+            friendly_fire: true,
+            config: Some("{\n    \"a\": 1\n}\n".to_string()),
+        };
+        let frames = vec![
+            (0.016, vec![Input::Resize(800, 600), Input::Control(EvThrust(0, 1.0))]),
+            (0.017, vec![]),
+            (0.015, vec![Input::Control(EvTurn(1, -1.0)), Input::Bullet(EvShoot(1, true)),
+                         Input::Pause, Input::Glow, Input::Debug]),
+        ];
+        {
+            let mut recorder = Recorder::create(&path, 12345, &options).unwrap();
+            for (i, &(delta, ref inputs)) in frames.iter().enumerate() {
+                recorder.record_frame(i as u64, delta, inputs).unwrap();
+            }
+        }
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.seed, 12345);
+        assert_eq!(replay.options, Options {
+            config: Some("{ \"a\": 1 }".to_string()),
+            .. options
+        });
+        assert_eq!(replay.deltas, frames.iter().map(|&(d, _)| d).collect::<Vec<_>>());
+        let mut player = Player::new(replay);
+        for (i, &(_, ref inputs)) in frames.iter().enumerate() {
+            assert_eq!(&player.fetch(i as u64), inputs);
+        }
+    }
+
+    #[test]
+    fn load_without_seed() {
+        let path = env::temp_dir().join("yasteroids-load-without-seed.txt");
+        File::create(&path).unwrap().write_all(b"players 1\n0 dt 0.016\n").unwrap();
+        let error = Replay::load(&path).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn load_malformed() {
+        let path = env::temp_dir().join("yasteroids-load-malformed.txt");
+        File::create(&path).unwrap().write_all(b"seed 1\n0 dt 0.016\n0 jump 0 1\n").unwrap();
+        let error = Replay::load(&path).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
 }
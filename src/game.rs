@@ -1,96 +1,287 @@
-use cgmath::{Rad, Point2, Vector2};
-use specs;
+use std::io;
+use std::sync::mpsc;
+use cgmath::{Point2, Rad};
 use gfx;
 use pegasus;
 
-use event::ReceiverHub;
+use config::{self, Config};
+use event::{self, ReceiverHub, SenderHub};
+use image::Image;
 use sys;
-use sys::draw::{Painter, Vertex};
+use sys::draw::{Layer, Painter, Sprite, Text, Vertex, View};
+use sys::raster::Rasterizer;
 use world;
 
 
 const SCREEN_EXTENTS: [f32; 2] = [10.0, 10.0];
+/// Number of different asteroid outlines to generate.
+const NUM_ASTEROID_SHAPES: usize = 16;
+/// Ship colors of the players, for the nose and the tail.
+const SHIP_COLORS: [(u32, u32); 2] = [(0xC04040FF, 0x20C020FF), (0x40A0E0FF, 0x2040C0FF)];
 
 pub struct Init {
-    hub: ReceiverHub,
-    vis_ship: world::Drawable,
+    input: sys::input::Source,
+    recorder: Option<event::Recorder>,
+    vis_ships: Vec<world::Drawable>,
     vis_bullet: world::Drawable,
-    vis_aster: world::Drawable,
+    vis_particle: world::Drawable,
+    vis_asters: Vec<world::Drawable>,
+    aster_shapes: Vec<sys::aster::Shape>,
+    /// Animations of the sprite visuals, by the visual index.
+    animations: Vec<(usize, world::Animation)>,
+    text_output: Option<mpsc::Sender<Vec<Text>>>,
+    view_output: Option<mpsc::Sender<View>>,
+    debug_output: Option<mpsc::Sender<Vec<world::DebugShape>>>,
+    seed: usize,
+    wrap: bool,
+    level_scale: f32,
+    follow: bool,
+    glow: bool,
+    config: Config,
+    num_players: usize,
+    friendly_fire: bool,
 }
 
 impl Init {
-    pub fn new<R, F>(factory: &mut F, painter: &mut Painter<R>, hub: ReceiverHub)
-               -> Init where
-    R: 'static + gfx::Resources,
-    F: gfx::Factory<R>,
+    // the visuals are the same for any painter, which is abstracted by `add_visual`
+    fn with_visuals<A>(mut add_visual: A, hub: ReceiverHub, seed: usize,
+                       text_output: mpsc::Sender<Vec<Text>>, view_output: mpsc::Sender<View>)
+                       -> Init where
+    A: FnMut(gfx::Primitive, gfx::state::Rasterizer, &[Vertex]) -> world::Drawable,
     {
+        let shapes = sys::aster::make_shapes(seed, NUM_ASTEROID_SHAPES);
         Init {
-            hub: hub,
-            vis_ship: {
+            input: sys::input::Source::Live(hub),
+            recorder: None,
+            vis_ships: SHIP_COLORS.iter().map(|&(nose, tail)| {
                 let rast = gfx::state::Rasterizer::new_fill();
-                painter.add_visual(factory,
-                    gfx::Primitive::TriangleList, rast, &[
-                    Vertex::new(-0.3, -0.5, 0x20C02000),
-                    Vertex::new(0.3, -0.5,  0x20C02000),
-                    Vertex::new(0.0, 0.5,   0xC0404000),
-                ])
-            },
+                let mut vis = add_visual(gfx::Primitive::TriangleList, rast, &[
+                    Vertex::new(-0.3, -0.5, tail),
+                    Vertex::new(0.3, -0.5,  tail),
+                    Vertex::new(0.0, 0.5,   nose),
+                ]);
+                vis.set_layer(Layer::Ship);
+                vis
+            }).collect(),
             vis_bullet: {
                 let mut rast = gfx::state::Rasterizer::new_fill();
                 rast.method = gfx::state::RasterMethod::Point;
-                painter.add_visual(factory,
-                    gfx::Primitive::PointList, rast, &[
-                    Vertex::new(0.0, 0.0, 0xFF808000),
-                ])
+                let mut vis = add_visual(gfx::Primitive::PointList, rast, &[
+                    Vertex::new(0.0, 0.0, 0xFF8080FF),
+                ]);
+                vis.set_layer(Layer::Bullet);
+                vis
             },
-            vis_aster: {
-                let rast = gfx::state::Rasterizer::new_fill();
-                painter.add_visual(factory,
-                    gfx::Primitive::TriangleStrip, rast, &[
-                    Vertex::new(-0.5, -0.5, 0xFFFFFF00),
-                    Vertex::new(0.5, -0.5,  0xFFFFFF00),
-                    Vertex::new(-0.5, 0.5,  0xFFFFFF00),
-                    Vertex::new(0.5, 0.5,   0xFFFFFF00),
-                ])
+            vis_particle: {
+                let mut rast = gfx::state::Rasterizer::new_fill();
+                rast.method = gfx::state::RasterMethod::Point;
+                let mut vis = add_visual(gfx::Primitive::PointList, rast, &[
+                    Vertex::new(0.0, 0.0, 0xFFFFFFFF),
+                ]);
+                vis.set_layer(Layer::Particle);
+                vis
             },
+            vis_asters: shapes.iter().map(|shape| {
+                let rast = gfx::state::Rasterizer::new_fill();
+                // a fan of triangles around the center
+                let n = shape.points.len();
+                let vertices: Vec<_> = (0 .. n).flat_map(|i| {
+                    let (a, b) = (shape.points[i], shape.points[(i + 1) % n]);
+                    vec![
+                        Vertex::new(0.0, 0.0,   0xC0C0C0FF),
+                        Vertex::new(a[0], a[1], 0xFFFFFFFF),
+                        Vertex::new(b[0], b[1], 0xFFFFFFFF),
+                    ]
+                }).collect();
+                let mut vis = add_visual(gfx::Primitive::TriangleList, rast, &vertices);
+                vis.set_layer(Layer::Asteroid);
+                vis
+            }).collect(),
+            aster_shapes: shapes,
+            animations: Vec::new(),
+            text_output: Some(text_output),
+            view_output: Some(view_output),
+            debug_output: None,
+            seed: seed,
+            wrap: false,
+            level_scale: 1.0,
+            follow: false,
+            glow: false,
+            config: Config::default(),
+            num_players: 1,
+            friendly_fire: false,
         }
     }
-}
 
+    pub fn new<R, F>(factory: &mut F, painter: &mut Painter<R>, hub: ReceiverHub, seed: usize)
+               -> Init where
+    R: 'static + gfx::Resources,
+    F: gfx::Factory<R>,
+    {
+        painter.set_extents(SCREEN_EXTENTS);
+        let text_output = painter.add_text(factory);
+        painter.add_glow(factory);
+        painter.add_stars(factory, seed);
+        let view_output = painter.get_view_sender();
+        let mut init = Init::with_visuals(|primitive, rast, vertices|
+            painter.add_visual(factory, primitive, rast, vertices),
+            hub, seed, text_output, view_output);
+        init.debug_output = Some(painter.add_debug(factory));
+        init
+    }
+
+    /// Prepare the game for drawing on the CPU, without any GPU resources.
+    pub fn software(rasterizer: &mut Rasterizer, hub: ReceiverHub, seed: usize) -> Init {
+        rasterizer.set_extents(SCREEN_EXTENTS);
+        let text_output = rasterizer.add_text();
+        rasterizer.add_stars(seed);
+        let view_output = rasterizer.get_view_sender();
+        Init::with_visuals(|primitive, _, vertices| rasterizer.add_visual(primitive, vertices),
+            hub, seed, text_output, view_output)
+    }
 
-fn create_ship(drawable: world::Drawable, world: &mut specs::World) -> specs::Entity {
-    world.create_now()
-         .with(drawable)
-         .with(world::Spatial {
-             pos: Point2::new(0.0, 0.0),
-             orient: Rad{ s: 0.0 },
-             scale: 1.0,
-         })
-         .with(world::Inertial {
-             velocity: Vector2::new(0.0, 0.0),
-             angular_velocity: Rad{ s:0.0 },
-         })
-         .with(world::Control {
-             thrust_speed: 4.0,
-             turn_speed: -4.0,
-         })
-         .with(world::Collision {
-            radius: 0.2,
-            health: 3,
-            damage: 2,
-         })
-         .build()
-}
+    /// Prepare the game without any GPU resources.
+    /// The drawing systems are left out, everything else is simulated.
+    pub fn headless(hub: ReceiverHub, seed: usize) -> Init {
+        Init {
+            input: sys::input::Source::Live(hub),
+            recorder: None,
+            vis_ships: vec![world::Drawable::stub(); SHIP_COLORS.len()],
+            vis_bullet: world::Drawable::stub(),
+            vis_particle: world::Drawable::stub(),
+            vis_asters: vec![world::Drawable::stub(); NUM_ASTEROID_SHAPES],
+            aster_shapes: sys::aster::make_shapes(seed, NUM_ASTEROID_SHAPES),
+            animations: Vec::new(),
+            text_output: None,
+            view_output: None,
+            debug_output: None,
+            seed: seed,
+            wrap: false,
+            level_scale: 1.0,
+            follow: false,
+            glow: false,
+            config: Config::default(),
+            num_players: 1,
+            friendly_fire: false,
+        }
+    }
+
+    /// Set the number of local players, each controlling a ship.
+    pub fn players(mut self, num_players: usize) -> Init {
+        assert!(num_players > 0);
+        self.num_players = num_players;
+        self
+    }
+
+    /// Let the bullets hit the ships of other players.
+    pub fn friendly_fire(mut self) -> Init {
+        self.friendly_fire = true;
+        self
+    }
+
+    /// Use the given tuning values instead of the default ones.
+    pub fn with_config(mut self, config: Config) -> Init {
+        self.config = config;
+        self
+    }
+
+    /// Write all the input events into the recorder.
+    pub fn record(mut self, recorder: event::Recorder) -> Init {
+        self.recorder = Some(recorder);
+        self
+    }
 
-pub struct Game {
-    player: specs::Entity,
+    /// Make the level bigger than the screen, by the given factor.
+    pub fn level_scale(mut self, scale: f32) -> Init {
+        assert!(scale >= 1.0);
+        self.level_scale = scale;
+        self
+    }
+
+    /// Keep the camera on the ship of the first player.
+    pub fn follow_camera(mut self) -> Init {
+        self.follow = true;
+        self
+    }
+
+    /// Start with the vector display look, which can be toggled later.
+    pub fn glow(mut self) -> Init {
+        self.glow = true;
+        self
+    }
+
+    /// Make the space toroidal, wrapping everything around the level edges.
+    pub fn wrap_around(mut self) -> Init {
+        self.wrap = true;
+        self
+    }
+
+    /// Replace the placeholder shapes with the sprites of the config,
+    /// turned into visuals by `add_sprite`. Call it after `with_config`.
+    pub fn sprites<A>(mut self, mut add_sprite: A) -> io::Result<Init> where
+    A: FnMut(&Image, &Sprite) -> world::Drawable,
+    {
+        let sprites = self.config.sprites.clone();
+        if let Some(ref sprite) = sprites.ship {
+            let vis = try!(self.load_sprite(&mut add_sprite, sprite, Layer::Ship));
+            for v in self.vis_ships.iter_mut() {
+                *v = vis.clone();
+            }
+        }
+        if let Some(ref sprite) = sprites.bullet {
+            self.vis_bullet = try!(self.load_sprite(&mut add_sprite, sprite, Layer::Bullet));
+        }
+        if let Some(ref sprite) = sprites.particle {
+            self.vis_particle = try!(self.load_sprite(&mut add_sprite, sprite, Layer::Particle));
+        }
+        if let Some(ref sprite) = sprites.asteroid {
+            // the rocks still differ in orientation and size
+            let vis = try!(self.load_sprite(&mut add_sprite, sprite, Layer::Asteroid));
+            for v in self.vis_asters.iter_mut() {
+                *v = vis.clone();
+            }
+        }
+        Ok(self)
+    }
+
+    fn load_sprite<A>(&mut self, add_sprite: &mut A, config: &config::Sprite, layer: Layer)
+                      -> io::Result<world::Drawable> where
+    A: FnMut(&Image, &Sprite) -> world::Drawable,
+    {
+        let image = try!(Image::load(&config.path));
+        let mut sprite = Sprite::new(config.extents).frames(config.columns, config.rows);
+        if !config.smooth {
+            sprite = sprite.sampler(gfx::texture::SamplerInfo::new(
+                gfx::texture::FilterMethod::Scale, gfx::texture::WrapMode::Clamp));
+        }
+        let mut vis = add_sprite(&image, &sprite);
+        vis.set_layer(layer);
+        if sprite.get_num_frames() > 1 {
+            self.animations.push((vis.get_visual(), world::Animation {
+                num_frames: sprite.get_num_frames(),
+                rate: config.rate,
+                time: 0.0,
+                repeat: config.repeat,
+            }));
+        }
+        Ok(vis)
+    }
+
+    /// Take the input events from the player instead of the hub.
+    pub fn replay(mut self, player: event::Player) -> Init {
+        self.input = sys::input::Source::Replay(player);
+        self
+    }
 }
 
+
+pub struct Game;
+
 impl pegasus::Init for Init {
     type Shell = Game;
 
     fn start(self, plan: &mut pegasus::Planner) -> Game {
-        let player = {
+        {
             let w = plan.mut_world();
             w.register::<world::Spatial>();
             w.register::<world::Inertial>();
@@ -98,22 +289,85 @@ impl pegasus::Init for Init {
             w.register::<world::Bullet>();
             w.register::<world::Asteroid>();
             w.register::<world::Collision>();
-            create_ship(self.vis_ship, w)
-        };
-
-        plan.add_system(sys::control::System::new(self.hub.control), "control", 30);
-        plan.add_system(sys::draw::System::new(SCREEN_EXTENTS), "pre-draw", pegasus::DRAW_PRIORITY + 5);
-        plan.add_system(sys::inertia::System, "inertia", 15);
-        plan.add_system(sys::bullet::System::new(self.hub.bullet, player, self.vis_bullet), "bullet", 25);
-        plan.add_system(sys::aster::System::new(SCREEN_EXTENTS, self.vis_aster), "aster", 24);
-        plan.add_system(sys::physics::System::new(), "physics", 5);
-
-        Game {
-            player: player,
+            w.register::<world::Invulnerable>();
+            w.register::<world::Emitter>();
+            w.register::<world::Particle>();
+            w.register::<world::Tint>();
+            w.register::<world::Animation>();
+            w.register::<world::Camera>();
+            w.add_resource(world::Status {
+                players: (0 .. self.num_players).map(|_| world::PlayerStatus {
+                    score: 0,
+                    lives: self.config.ship.lives,
+                    phase: world::Phase::Spawning(0.0),
+                }).collect(),
+                wave: 1,
+            });
+            w.add_resource(world::Contacts(Vec::new()));
+            w.add_resource(world::Screen::new(SCREEN_EXTENTS, self.level_scale));
+            w.add_resource(world::Effects {
+                glow: self.glow,
+                debug: false,
+            });
+            w.add_resource(world::DebugShapes(Vec::new()));
+            w.create_now()
+                .with(world::Camera {
+                    pos: Point2::new(0.0, 0.0),
+                    rotation: Rad{ s: 0.0 },
+                    zoom: 1.0,
+                    target: if self.follow { Some(0) } else { None },
+                })
+                .build();
         }
-    }
 
-    fn proceed(game: &mut Game, world: &specs::World) -> bool {
-        world.is_alive(game.player)
+        let wrap = if self.wrap { Some(SCREEN_EXTENTS) } else { None };
+        let (hub_send, hub_recv) = SenderHub::new();
+        let pause = sys::PauseFlag::default();
+        // the debug shapes are taken away by the pre-draw system
+        let debug = self.view_output.is_some();
+        let input = sys::input::System::new(self.input, hub_send, self.recorder, pause.clone(), debug);
+        plan.add_system(input, "input", 40);
+        if let Some(output) = self.view_output {
+            let draw = sys::draw::System::new(output, self.debug_output, self.wrap,
+                                              self.config.glow);
+            plan.add_system(draw, "pre-draw", pegasus::DRAW_PRIORITY + 5);
+        }
+        if let Some(output) = self.text_output {
+            plan.add_system(sys::hud::System::new(output, pause.clone()), "hud", pegasus::DRAW_PRIORITY + 5);
+        }
+        // everything else stops while paused
+        let ship = sys::ship::System::new(self.vis_ships, self.config.ship,
+                                           self.config.tint.blink_period);
+        plan.add_system(sys::Pausable::new(pause.clone(), ship), "ship", 35);
+        let control = sys::control::System::new(hub_recv.control);
+        plan.add_system(sys::Pausable::new(pause.clone(), control), "control", 30);
+        let anim = sys::anim::System::new(self.animations);
+        plan.add_system(sys::Pausable::new(pause.clone(), anim), "anim", 20);
+        // after the pre-draw, so that the frames are drawn the same every time
+        let inertia = sys::inertia::System::new(wrap);
+        plan.add_system(sys::Pausable::new(pause.clone(), inertia), "inertia", 14);
+        let camera = sys::camera::System::new(self.wrap);
+        plan.add_system(sys::Pausable::new(pause.clone(), camera), "camera", 12);
+        let bullet = sys::bullet::System::new(hub_recv.bullet, self.vis_bullet, self.config.bullet);
+        plan.add_system(sys::Pausable::new(pause.clone(), bullet), "bullet", 25);
+        let physics = sys::physics::System::new(wrap, self.friendly_fire);
+        plan.add_system(sys::Pausable::new(pause.clone(), physics), "physics", 5);
+        // these react on the contacts of the physics, each with its own priority:
+        // the systems of the same priority start in the order they finished
+        // on the previous frame, which would make the simulation differ
+        let score = sys::score::System::new(self.config.asteroid.kinds.clone());
+        plan.add_system(sys::Pausable::new(pause.clone(), score), "score", 4);
+        let particle = sys::particle::System::new(self.vis_particle, self.seed, self.config.particle);
+        plan.add_system(sys::Pausable::new(pause.clone(), particle), "particle", 3);
+        let tint = sys::tint::System::new(self.config.tint);
+        plan.add_system(sys::Pausable::new(pause.clone(), tint), "tint", 2);
+        let asters = self.vis_asters.into_iter()
+            .zip(self.aster_shapes.iter().map(|shape| shape.radius))
+            .collect();
+        let aster = sys::aster::System::new(SCREEN_EXTENTS, asters, self.seed, self.wrap,
+                                            self.config.asteroid);
+        plan.add_system(sys::Pausable::new(pause, aster), "aster", 1);
+
+        Game
     }
 }
@@ -0,0 +1,164 @@
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use pegasus::{self, Init};
+use specs;
+
+use game;
+use sys;
+use sys::raster::Rasterizer;
+use world;
+
+
+/// Simulation time step of the headless runner.
+pub const TIME_STEP: sys::Delta = 1.0 / 60.0;
+
+/// Drives the game systems with a fixed time step,
+/// without creating a window or a graphics device.
+/// Given the same seed and the same input events on the same frames,
+/// the simulation always ends up in the same world state.
+pub struct Runner {
+    planner: pegasus::Planner,
+    game: game::Game,
+    frame: u64,
+}
+
+impl Runner {
+    pub fn new(init: game::Init) -> Runner {
+        let mut w = specs::World::new();
+        // the visuals are still attached to the entities,
+        // there is just nobody to draw them
+        w.register::<world::Drawable>();
+        let mut planner = specs::Planner::new(w, 4);
+        let game = init.start(&mut planner);
+        Runner {
+            planner: planner,
+            game: game,
+            frame: 0,
+        }
+    }
+
+    /// Number of frames simulated so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Simulate one frame. Returns false when the game is over.
+    pub fn step(&mut self) -> bool {
+        self.step_by(TIME_STEP)
+    }
+
+    /// Simulate one frame with a custom time step, e.g. the one
+    /// recorded in a replay.
+    pub fn step_by(&mut self, delta: sys::Delta) -> bool {
+        self.planner.dispatch(delta);
+        self.frame += 1;
+        game::Init::proceed(&mut self.game, self.planner.mut_world()) &&
+            !self.status().is_game_over()
+    }
+
+    /// Current scores, lives, and phases of the players.
+    pub fn status(&mut self) -> world::Status {
+        self.world().read_resource::<world::Status>().clone()
+    }
+
+    /// Simulate up to the given number of frames, or until the game is over.
+    pub fn run(&mut self, num_frames: u64) {
+        for _ in 0 .. num_frames {
+            if !self.step() {
+                break
+            }
+        }
+    }
+
+    /// Wait for the systems to finish, and access the world.
+    pub fn world(&mut self) -> &mut specs::World {
+        self.planner.wait();
+        self.planner.mut_world()
+    }
+
+    /// Draw the current state of the world on the CPU.
+    pub fn render(&mut self, rasterizer: &mut Rasterizer) {
+        use specs::Join;
+        let w = self.world();
+        let draw = w.read::<world::Drawable>();
+        rasterizer.render((&draw).iter());
+    }
+
+    /// Compute a fingerprint of the simulated state, useful for checking
+    /// that two runs ended up in the same place.
+    pub fn state_hash(&mut self) -> u64 {
+        use specs::Join;
+        let mut hasher = DefaultHasher::new();
+        let w = self.world();
+        let (space, inertia, collision, entities) = (w.read::<world::Spatial>(),
+            w.read::<world::Inertial>(), w.read::<world::Collision>(), w.entities());
+        for (s, e) in (&space, &entities).iter() {
+            e.hash(&mut hasher);
+            s.pos.x.to_bits().hash(&mut hasher);
+            s.pos.y.to_bits().hash(&mut hasher);
+            s.orient.s.to_bits().hash(&mut hasher);
+            s.scale.to_bits().hash(&mut hasher);
+            if let Some(i) = inertia.get(e) {
+                i.velocity.x.to_bits().hash(&mut hasher);
+                i.velocity.y.to_bits().hash(&mut hasher);
+                i.angular_velocity.s.to_bits().hash(&mut hasher);
+            }
+            if let Some(c) = collision.get(e) {
+                c.health.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use event::{Input, SenderHub};
+    use game;
+    use image::Image;
+    use sys::{bullet, control};
+    use sys::raster::Rasterizer;
+    use super::Runner;
+
+    /// Seed, number of frames, and size of the golden frame.
+    /// Render it again with
+    /// `cargo run -- --seed 7 --headless 600 --render golden/seed7-600frames-640x480.png`
+    /// whenever the picture is supposed to change.
+    const GOLDEN_SEED: usize = 7;
+    const GOLDEN_FRAMES: u64 = 600;
+    const GOLDEN_PATH: &'static str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/golden/seed7-600frames-640x480.png");
+
+    fn run_hash(seed: usize) -> u64 {
+        let (send, recv) = SenderHub::new();
+        send.send(Input::Control(control::Event::EvThrust(0, 1.0)));
+        send.send(Input::Control(control::Event::EvTurn(0, 0.5)));
+        send.send(Input::Bullet(bullet::Event::EvShoot(0, true)));
+        let mut runner = Runner::new(game::Init::headless(recv, seed));
+        runner.run(300);
+        runner.state_hash()
+    }
+
+    #[test]
+    fn same_seed_same_state() {
+        assert_eq!(run_hash(5), run_hash(5));
+    }
+
+    #[test]
+    fn other_seed_other_state() {
+        assert!(run_hash(5) != run_hash(6));
+    }
+
+    #[test]
+    fn golden_frame() {
+        let (send, recv) = SenderHub::new();
+        send.send(Input::Resize(::RENDER_SIZE[0], ::RENDER_SIZE[1]));
+        let mut rasterizer = Rasterizer::new(::RENDER_SIZE);
+        let mut runner = Runner::new(game::Init::software(&mut rasterizer, recv, GOLDEN_SEED));
+        runner.run(GOLDEN_FRAMES);
+        runner.render(&mut rasterizer);
+        let golden = Image::load(GOLDEN_PATH).expect("Unable to load the golden image");
+        let diff = rasterizer.get_image().diff(&golden, ::GOLDEN_THRESHOLD);
+        assert!(diff <= ::GOLDEN_TOLERANCE, "{:.3}% of the frame differs", 100.0 * diff);
+    }
+}
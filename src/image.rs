@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use png;
+
+
+/// An RGBA picture with 8 bits per channel, stored top row first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub size: [u32; 2],
+    pub pixels: Vec<[u8; 4]>,
+}
+
+fn invalid<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl Image {
+    pub fn new(size: [u32; 2], color: [u8; 4]) -> Image {
+        Image {
+            size: size,
+            pixels: vec![color; (size[0] * size[1]) as usize],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let file = try!(File::open(path));
+        let decoder = png::Decoder::new(file);
+        let (info, mut reader) = try!(decoder.read_info().map_err(invalid));
+        if info.bit_depth != png::BitDepth::Eight {
+            return Err(invalid(format!("unsupported bit depth {:?}", info.bit_depth)))
+        }
+        let mut data = vec![0; info.buffer_size()];
+        try!(reader.next_frame(&mut data).map_err(invalid));
+        let pixels = match info.color_type {
+            png::ColorType::RGBA => data.chunks(4)
+                .map(|c| [c[0], c[1], c[2], c[3]]).collect(),
+            png::ColorType::RGB => data.chunks(3)
+                .map(|c| [c[0], c[1], c[2], 0xFF]).collect(),
+            other => return Err(invalid(format!("unsupported color type {:?}", other))),
+        };
+        Ok(Image {
+            size: [info.width, info.height],
+            pixels: pixels,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        use png::HasParameters;
+        let file = try!(File::create(path));
+        let mut encoder = png::Encoder::new(file, self.size[0], self.size[1]);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = try!(encoder.write_header().map_err(invalid));
+        let data: Vec<u8> = self.pixels.iter().flat_map(|p| p.iter().cloned()).collect();
+        writer.write_image_data(&data).map_err(invalid)
+    }
+
+    /// Share of the pixels that differ from the other image by more than
+    /// the threshold in any channel, from zero to one.
+    /// Images of different sizes are entirely different.
+    pub fn diff(&self, other: &Image, threshold: u8) -> f32 {
+        if self.size != other.size {
+            return 1.0
+        }
+        let count = self.pixels.iter().zip(other.pixels.iter()).filter(|&(a, b)|
+            a.iter().zip(b.iter()).any(|(&x, &y)| (x as i16 - y as i16).abs() > threshold as i16)
+        ).count();
+        count as f32 / self.pixels.len().max(1) as f32
+    }
+}
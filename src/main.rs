@@ -1,51 +1,340 @@
 extern crate cgmath;
 #[macro_use]
 extern crate gfx;
+extern crate gfx_gl as gl;
 extern crate gfx_window_glutin;
+#[cfg(feature = "gamepad")]
+extern crate gilrs;
 extern crate glutin;
 extern crate pegasus;
+extern crate png;
 extern crate specs;
 extern crate rand;
+extern crate rustc_serialize;
 
+mod binding;
+mod capture;
+mod config;
 mod event;
 mod game;
+mod headless;
+mod image;
 mod world;
 mod sys;
 
-type DepthFormat = gfx::format::Depth;
 
-static USAGE: &'static str = "
-Controls:
-    A - thrust
-    S - shoot
-    Left/Right - turn
+/// Size of the frames drawn by the headless runs, in pixels.
+const RENDER_SIZE: [u32; 2] = [640, 480];
+/// Difference in a color channel that is still considered a match,
+/// leaving room for the rounding of the colors.
+const GOLDEN_THRESHOLD: u8 = 2;
+/// Share of the pixels allowed to differ from the golden image.
+const GOLDEN_TOLERANCE: f32 = 0.001;
+
+static OPTIONS: &'static str = "
+Options:
+    --bindings <file> - load the input bindings from a file
+    --config <file> - load the game tuning values from a file
+    --headless <frames> - simulate without a window, or replay up to that many frames
+    --render <file> - draw the last headless frame into a PNG file
+    --golden <file> - compare the last headless frame with a PNG file
+    --seed <number> - make the simulation reproducible
+    --wrap - wrap the space around the level edges
+    --level <scale> - make the level bigger than the screen
+    --follow - keep the camera on the first ship
+    --glow - start with the vector display look
+    --players <number> - play with several ships on one keyboard
+    --friendly-fire - let the players shoot each other
+    --capture <n> - save every n-th frame into a PNG file
+    --record <file> - write the input events into a replay file
+    --replay <file> - play the input events back from a replay file,
+        exactly with --headless, at the pace of the window otherwise,
+        with the seed and the game options of the recording
+
+Press F12 to save a screenshot.
 ";
 
+fn run_headless(init: game::Init, num_frames: u64, deltas: Option<Vec<sys::Delta>>,
+                rasterizer: Option<&mut sys::raster::Rasterizer>) {
+    use specs::Join;
+    let mut runner = headless::Runner::new(init);
+    match deltas {
+        // follow the recorded time steps to reproduce the session exactly
+        Some(deltas) => for delta in deltas.into_iter().take(num_frames as usize) {
+            if !runner.step_by(delta) {
+                break
+            }
+        },
+        None => runner.run(num_frames),
+    }
+    let frame = runner.frame();
+    for (i, ps) in runner.status().players.iter().enumerate() {
+        println!("Player {}: score {}, lives {}, {:?}", i + 1, ps.score, ps.lives, ps.phase);
+    }
+    let w = runner.world();
+    let num_asteroids = (&w.read::<world::Asteroid>()).iter().count();
+    println!("Simulated {} frames, {} asteroids alive, wave {}",
+        frame, num_asteroids, w.read_resource::<world::Status>().wave);
+    println!("State hash: {:016x}", runner.state_hash());
+    if let Some(rasterizer) = rasterizer {
+        runner.render(rasterizer);
+    }
+}
+
+// returns false if the frame doesn't match
+fn check_frame(frame: &image::Image, render_path: Option<String>, golden_path: Option<String>)
+               -> bool {
+    if let Some(path) = render_path {
+        frame.save(&path).expect("Unable to save the frame");
+        println!("Frame saved to {}", path);
+    }
+    if let Some(path) = golden_path {
+        let golden = image::Image::load(&path).expect("Unable to load the golden image");
+        let diff = frame.diff(&golden, GOLDEN_THRESHOLD);
+        println!("Difference from {}: {:.3}%", path, 100.0 * diff);
+        return diff <= GOLDEN_TOLERANCE
+    }
+    true
+}
+
 pub fn main() {
-    println!("{}", USAGE);
+    let mut headless_frames = None;
+    let mut render_path = None;
+    let mut golden_path = None;
+    let mut seed = rand::random();
+    let mut record_path = None;
+    let mut capture = capture::Capture::new();
+    let mut replay = None;
+    let mut options = event::Options::default();
+    let mut config_path = None;
+    let mut follow = false;
+    let mut glow = false;
+    let mut bindings = binding::Bindings::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => {
+                headless_frames = Some(args.next()
+                    .and_then(|s| s.parse().ok())
+                    .expect("--headless expects a number of frames"));
+            },
+            "--render" => {
+                render_path = Some(args.next()
+                    .expect("--render expects a file path"));
+            },
+            "--golden" => {
+                golden_path = Some(args.next()
+                    .expect("--golden expects a file path"));
+            },
+            "--seed" => {
+                seed = args.next()
+                    .and_then(|s| s.parse().ok())
+                    .expect("--seed expects a number");
+            },
+            "--bindings" => {
+                let path = args.next()
+                    .expect("--bindings expects a file path");
+                bindings = binding::Bindings::load(&path)
+                    .expect("Unable to load the bindings");
+            },
+            "--config" => {
+                let path = args.next()
+                    .expect("--config expects a file path");
+                options.config = match config::Config::read(&path) {
+                    Ok(text) => Some(text),
+                    Err(e) => {
+                        println!("Error in {}: {}", path, e);
+                        return
+                    },
+                };
+                config_path = Some(path);
+            },
+            "--wrap" => {
+                options.wrap = true;
+            },
+            "--level" => {
+                options.level_scale = args.next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|&s: &f32| s >= 1.0)
+                    .expect("--level expects a scale of at least one");
+            },
+            "--follow" => {
+                follow = true;
+            },
+            "--glow" => {
+                glow = true;
+            },
+            "--players" => {
+                options.players = args.next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|&n| n > 0)
+                    .expect("--players expects a positive number");
+            },
+            "--friendly-fire" => {
+                options.friendly_fire = true;
+            },
+            "--capture" => {
+                capture = capture.every(args.next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|&n| n > 0)
+                    .expect("--capture expects a positive number of frames"));
+            },
+            "--record" => {
+                record_path = Some(args.next()
+                    .expect("--record expects a file path"));
+            },
+            "--replay" => {
+                let path = args.next()
+                    .expect("--replay expects a file path");
+                replay = Some(event::Replay::load(&path)
+                    .expect("Unable to load the replay"));
+            },
+            other => panic!("Unknown argument: {}", other),
+        }
+    }
+    println!("\nControls:\n{}{}", bindings.describe(), OPTIONS);
+    if let Some(ref r) = replay {
+        // the session only repeats with the recorded settings
+        seed = r.seed;
+        options = r.options.clone();
+        config_path = Some("the replay".to_string());
+    }
+    println!("Using seed {}", seed);
+    let config = match options.config {
+        Some(ref text) => match config::Config::parse(text) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                println!("Error in {}: {}", config_path.unwrap_or_default(), e);
+                return
+            },
+        },
+        None => None,
+    };
 
+    let (mut ev_send, ev_recv) = event::SenderHub::new();
+    let replaying = replay.is_some();
+    let deltas = replay.as_ref().map(|r| r.deltas.clone());
+    let configure = move |mut init: game::Init| {
+        if options.wrap {
+            init = init.wrap_around();
+        }
+        if options.friendly_fire {
+            init = init.friendly_fire();
+        }
+        if follow {
+            init = init.follow_camera();
+        }
+        if glow {
+            init = init.glow();
+        }
+        init = init.level_scale(options.level_scale);
+        init = init.players(options.players);
+        if let Some(config) = config {
+            init = init.with_config(config);
+        }
+        if let Some(path) = record_path {
+            let recorder = event::Recorder::create(path, seed, &options)
+                .expect("Unable to create the replay file");
+            init = init.record(recorder);
+        }
+        if let Some(replay) = replay {
+            init = init.replay(event::Player::new(replay));
+        }
+        init
+    };
+
+    if let Some(num_frames) = headless_frames {
+        if !replaying {
+            // drawing the frame or not, the level has the same shape
+            ev_send.send(event::Input::Resize(RENDER_SIZE[0], RENDER_SIZE[1]));
+        }
+        if render_path.is_none() && golden_path.is_none() {
+            let init = configure(game::Init::headless(ev_recv, seed));
+            return run_headless(init, num_frames, deltas, None);
+        }
+        let mut rasterizer = sys::raster::Rasterizer::new(RENDER_SIZE);
+        let init = configure(game::Init::software(&mut rasterizer, ev_recv, seed))
+            .sprites(|image, sprite| rasterizer.add_sprite(image, sprite))
+            .expect("Unable to load the sprites");
+        run_headless(init, num_frames, deltas, Some(&mut rasterizer));
+        if !check_frame(rasterizer.get_image(), render_path, golden_path) {
+            println!("The frame doesn't match the golden image");
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if replaying {
+        // the simulation follows the frame times of the window,
+        // only the headless runner can follow the recorded ones
+        println!("Replaying at the pace of the window, the session may not repeat exactly");
+    }
     let title = "Asteroids demo for gfx-rs, specs, and pegasus";
-    let (ev_send, ev_recv) = event::SenderHub::new();
 
     let builder = glutin::WindowBuilder::new()
         .with_title(title.to_string())
         .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 2)));
-    let (window, device, mut factory, main_color, _main_depth) =
-        gfx_window_glutin::init::<sys::draw::ColorFormat, DepthFormat>(builder);
+    let (window, device, mut factory, mut main_color, mut main_depth) =
+        gfx_window_glutin::init::<sys::draw::ColorFormat, sys::draw::DepthFormat>(builder);
 
-    let mut painter = sys::draw::Painter::new(main_color);
-    let init = game::Init::new(&mut factory, &mut painter, ev_recv);
+    let mut painter = sys::draw::Painter::new(main_color.clone(), main_depth.clone());
+    let (width, height) = window.get_inner_size_pixels().unwrap_or((1, 1));
+    let mut size = [width, height];
+    painter.set_target((main_color.clone(), main_depth.clone(), [width, height], None));
+    let target_send = painter.get_target_sender();
+    if !replaying {
+        // the playfield takes the shape of the window
+        ev_send.send(event::Input::Resize(width, height));
+    }
+    let init = configure(game::Init::new(&mut factory, &mut painter, ev_recv, seed))
+        .sprites(|image, sprite| painter.add_sprite(&mut factory, image, sprite))
+        .expect("Unable to load the sprites");
 
     let mut pegasus = pegasus::Pegasus::new(init, device, painter, ||
         factory.create_command_buffer());
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = gilrs::Gilrs::new();
     
-    'main: while let Some(_swing) = pegasus.swing() {
+    'main: while pegasus.swing().is_some() {
+        // the frame is still in the back buffer
+        if let Some(path) = capture.next_frame() {
+            let mut frame = None;
+            unsafe {
+                pegasus.device.with_gl(|gl| frame = Some(capture::read_frame(gl, size)));
+            }
+            match frame.unwrap().save(&path) {
+                Ok(()) => println!("Frame saved to {}", path),
+                Err(e) => println!("Unable to save {}: {}", path, e),
+            }
+        }
         window.swap_buffers().unwrap();
         for event in window.poll_events() {
             match event {
                 glutin::Event::KeyboardInput(_, _, Some(glutin::VirtualKeyCode::Escape)) |
                 glutin::Event::Closed => break 'main,
-                _ => ev_send.process_glutin(event),
+                glutin::Event::KeyboardInput(glutin::ElementState::Pressed, _,
+                                             Some(glutin::VirtualKeyCode::F12)) => {
+                    capture.request_shot();
+                },
+                glutin::Event::Resized(width, height) => {
+                    size = [width, height];
+                    gfx_window_glutin::update_views(&window, &mut main_color, &mut main_depth);
+                    let post = sys::draw::PostTargets::new(&mut factory, size);
+                    let _ = target_send.send((main_color.clone(), main_depth.clone(), size,
+                                              Some(post)));
+                    if !replaying {
+                        ev_send.send(event::Input::Resize(width, height));
+                    }
+                },
+                // the keyboard is ignored while playing a replay
+                _ if replaying => (),
+                _ => ev_send.process_glutin(event, &bindings),
+            }
+        }
+        #[cfg(feature = "gamepad")]
+        for (_, event) in gamepads.poll_events() {
+            if !replaying {
+                ev_send.process_gilrs(event, &bindings);
             }
         }
     }
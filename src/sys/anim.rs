@@ -0,0 +1,46 @@
+use specs;
+use world as w;
+
+
+// the animation system flips through the frames of the sprites,
+// starting an animation on every new entity with an animated visual
+pub struct System {
+    /// Animation of each animated visual, by the visual index.
+    animations: Vec<(usize, w::Animation)>,
+}
+
+impl System {
+    pub fn new(animations: Vec<(usize, w::Animation)>) -> System {
+        System {
+            animations: animations,
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        use specs::Join;
+        let (mut anim, mut draw, entities) = arg.fetch(|w|
+            (w.write::<w::Animation>(), w.write::<w::Drawable>(), w.entities())
+        );
+        if !self.animations.is_empty() {
+            let mut started = Vec::new();
+            for (d, e) in (&draw, &entities).iter() {
+                if anim.get(e).is_some() {
+                    continue
+                }
+                let visual = d.get_visual();
+                if let Some(&(_, ref a)) = self.animations.iter().find(|&&(v, _)| v == visual) {
+                    started.push((e, a.clone()));
+                }
+            }
+            for (e, a) in started {
+                anim.insert(e, a);
+            }
+        }
+        for (a, d) in (&mut anim, &mut draw).iter() {
+            a.time += time;
+            d.set_frame(a.get_frame());
+        }
+    }
+}
@@ -1,63 +1,174 @@
-use rand::{Rng, StdRng};
-use cgmath::{Deg, Rad, Point2};
+use rand::{Rng, SeedableRng, StdRng};
+use cgmath::{Deg, Rad, Point2, Vector2, EuclideanVector};
 use specs;
+use config;
 use world as w;
 
-//const KINDS: usize = 2;
+
+/// Outer radius of the generated outlines, matching the visual space.
+pub const SHAPE_RADIUS: f32 = 0.5;
+const MIN_POINTS: usize = 7;
+const MAX_POINTS: usize = 14;
+/// How deep the dents of an outline can go, relative to its radius.
+const JAGGEDNESS: f32 = 0.4;
+/// How much smaller than its kind a rock can get, so that
+/// the ones sharing an outline don't look the same.
+const SIZE_VARIATION: f32 = 0.2;
+
+/// Irregular asteroid outline, going around the center.
+#[derive(Clone, Debug)]
+pub struct Shape {
+    pub points: Vec<[f32; 2]>,
+    /// Average distance of the outline from the center.
+    pub radius: f32,
+}
+
+/// Generate a number of different outlines, deterministically for the seed.
+pub fn make_shapes(seed: usize, count: usize) -> Vec<Shape> {
+    use std::f32::consts::PI;
+    // keep the sequence apart from the one used for spawning
+    let mut rng = StdRng::from_seed(&[seed, 1]);
+    (0 .. count).map(|_| {
+        let num = rng.gen_range(MIN_POINTS, MAX_POINTS + 1);
+        let step = 2.0 * PI / num as f32;
+        let points: Vec<_> = (0 .. num).map(|i| {
+            let angle = (i as f32 + rng.gen_range(-0.3, 0.3)) * step;
+            let dist = SHAPE_RADIUS * (1.0 - rng.gen_range(0.0, JAGGEDNESS));
+            [dist * angle.cos(), dist * angle.sin()]
+        }).collect();
+        let total = points.iter().fold(0.0, |sum, p| sum + (p[0]*p[0] + p[1]*p[1]).sqrt());
+        Shape {
+            radius: total / num as f32,
+            points: points,
+        }
+    }).collect()
+}
 
 pub struct System {
     screen_ext: [f32; 2],
     spawn_radius: f32,
-    rate: f32,
     time_left: super::Delta,
-    drawable: w::Drawable,
+    /// Visual and collision radius of each outline.
+    shapes: Vec<(w::Drawable, f32)>,
     rng: StdRng,
+    wrap: bool,
+    /// Number of large asteroids spawned so far.
+    spawned: u32,
+    config: config::Asteroid,
 }
 
 impl System {
-    pub fn new(extents: [f32; 2], drawable: w::Drawable) -> System {
+    pub fn new(extents: [f32; 2], shapes: Vec<(w::Drawable, f32)>, seed: usize, wrap: bool,
+               config: config::Asteroid) -> System {
+        assert!(!shapes.is_empty());
         let radius = extents[0] + extents[1];
         System {
             screen_ext: extents,
             spawn_radius: radius,
-            rate: 1.0,
-            time_left: 3.0,
-            drawable: drawable,
-            rng: StdRng::new().unwrap(),
+            time_left: config.head_start,
+            shapes: shapes,
+            rng: StdRng::from_seed(&[seed]),
+            wrap: wrap,
+            spawned: 0,
+            config: config,
         }
     }
 
-    fn spawn(&mut self, w: &specs::World) -> specs::Entity {
-        let origin_angle: Rad<_> = Deg{ s: self.rng.gen_range(0f32, 360f32) }.into();
-        let origin_pos = Point2::new(
-            self.spawn_radius * f32::cos(origin_angle.s),
-            self.spawn_radius * f32::sin(origin_angle.s),
-        );
-        let target = Point2::new(
-            self.rng.gen_range(-self.screen_ext[0], self.screen_ext[0]),
-            self.rng.gen_range(-self.screen_ext[1], self.screen_ext[1]),
-        );
+    fn create(&mut self, w: &specs::World, kind: u8, pos: Point2<f32>, velocity: Vector2<f32>)
+              -> specs::Entity {
+        let (scale, health) = {
+            let k = &self.config.kinds[kind as usize];
+            (k.scale, k.health)
+        };
+        let shape = self.rng.gen_range(0, self.shapes.len());
+        let orient = self.rng.gen_range(0.0, 2.0 * ::std::f32::consts::PI);
+        let size = 1.0 - self.rng.gen_range(0.0, SIZE_VARIATION);
+        // the collision follows the outline of the rock
+        let radius = size * scale * self.shapes[shape].1;
+        // an empty range can't be sampled
+        let spin = if self.config.spin > 0.0 {
+            self.rng.gen_range(-self.config.spin, self.config.spin)
+        } else {
+            0.0
+        };
         w.create_later_build()
             .with(w::Spatial {
-                pos: origin_pos,
-                orient: Rad{ s: 0.0 },
-                scale: 1.0,
+                pos: pos,
+                orient: Rad{ s: orient },
+                scale: size * scale,
             })
-            .with(self.drawable.clone())
+            .with(self.shapes[shape].0.clone())
             .with(w::Inertial {
-                velocity: (target - origin_pos) * 0.1,
-                angular_velocity: Rad{ s: self.rng.gen_range(-2.0, 2.0) },
+                velocity: velocity,
+                angular_velocity: Rad{ s: spin },
             })
             .with(w::Asteroid {
-                kind: 0,
+                kind: kind,
             })
             .with(w::Collision {
-                radius: 0.5,
-                health: 1,
-                damage: 2,
+                radius: radius,
+                health: health,
+                damage: self.config.damage,
             })
             .build()
     }
+
+    fn spawn(&mut self, w: &specs::World) -> specs::Entity {
+        let origin_pos = if self.wrap {
+            // there is no outside, so appear on the edge
+            if self.rng.gen() {
+                Point2::new(-self.screen_ext[0], self.rng.gen_range(-self.screen_ext[1], self.screen_ext[1]))
+            } else {
+                Point2::new(self.rng.gen_range(-self.screen_ext[0], self.screen_ext[0]), -self.screen_ext[1])
+            }
+        } else {
+            let origin_angle: Rad<_> = Deg{ s: self.rng.gen_range(0f32, 360f32) }.into();
+            Point2::new(
+                self.spawn_radius * f32::cos(origin_angle.s),
+                self.spawn_radius * f32::sin(origin_angle.s),
+            )
+        };
+        let target = Point2::new(
+            self.rng.gen_range(-self.screen_ext[0], self.screen_ext[0]),
+            self.rng.gen_range(-self.screen_ext[1], self.screen_ext[1]),
+        );
+        let velocity = (target - origin_pos).normalize() * self.config.kinds[0].speed;
+        self.spawned += 1;
+        w.write_resource::<w::Status>().wave = self.spawned / self.config.wave_size + 1;
+        self.create(w, 0, origin_pos, velocity)
+    }
+
+    // asteroids destroyed by the physics are broken into smaller ones
+    fn split(&mut self, w: &specs::World) {
+        let broken: Vec<_> = {
+            let (contacts, aster, space, inertia) = (w.read_resource::<w::Contacts>(),
+                w.read::<w::Asteroid>(), w.read::<w::Spatial>(), w.read::<w::Inertial>());
+            let mut broken = Vec::new();
+            for contact in contacts.0.iter() {
+                for (&e, &destroyed) in contact.entities.iter().zip(contact.destroyed.iter()) {
+                    if !destroyed || broken.iter().any(|&(e2, _, _, _)| e2 == e) {
+                        continue
+                    }
+                    if let (Some(a), Some(s), Some(i)) = (aster.get(e), space.get(e), inertia.get(e)) {
+                        broken.push((e, a.kind, s.pos, i.velocity));
+                    }
+                }
+            }
+            broken
+        };
+        for (_, kind, pos, velocity) in broken {
+            let next = kind + 1;
+            for _ in 0 .. self.config.kinds[kind as usize].fragments {
+                let angle: Rad<_> = Deg{ s: self.rng.gen_range(0f32, 360f32) }.into();
+                let dir = Vector2::new(angle.s.cos(), angle.s.sin());
+                let (radius, speed) = {
+                    let k = &self.config.kinds[next as usize];
+                    (k.scale * SHAPE_RADIUS, k.speed)
+                };
+                self.create(w, next, pos + dir * radius, velocity + dir * speed);
+            }
+        }
+    }
 }
 
 impl specs::System<super::Delta> for System {
@@ -65,12 +176,22 @@ impl specs::System<super::Delta> for System {
         use specs::Join;
         self.time_left += time;
         let (aster, space, inertia, entities) = arg.fetch(|w| {
-            while self.time_left >= self.rate {
-                self.time_left -= self.rate;
-                self.spawn(w);
+            // spawn around the level, whatever its shape is
+            self.screen_ext = w.read_resource::<w::Screen>().level;
+            self.spawn_radius = self.screen_ext[0] + self.screen_ext[1];
+            let count = (&w.read::<w::Asteroid>()).iter().count();
+            while self.time_left >= self.config.spawn_rate {
+                self.time_left -= self.config.spawn_rate;
+                if !self.wrap || count < self.config.max_wrapped {
+                    self.spawn(w);
+                }
             }
+            self.split(w);
             (w.read::<w::Asteroid>(), w.read::<w::Spatial>(), w.read::<w::Inertial>(), w.entities())
         });
+        if self.wrap {
+            return
+        }
         for (_, s, i, e) in (&aster, &space, &inertia, &entities).iter() {
             if  (s.pos.x.abs() > self.screen_ext[0] && s.pos.x * i.velocity.x >= 0.0) ||
                 (s.pos.y.abs() > self.screen_ext[1] && s.pos.y * i.velocity.y >= 0.0) {
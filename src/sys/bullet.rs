@@ -1,58 +1,95 @@
 use std::sync::mpsc;
 use cgmath::{Rad};
 use specs;
+use config;
 use world as w;
 
 
-const COOL_TIME: f32 = 0.1;
-
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Event {
-    EvShoot(bool),
+    EvShoot(w::PlayerId, bool),
+}
+
+#[derive(Clone, Copy)]
+struct Gun {
+    shoot: bool,
+    cool_time: f32,
 }
 
 pub struct System {
     input: mpsc::Receiver<Event>,
-    shoot: bool,
-    ship_entity: specs::Entity,
+    guns: Vec<Gun>,
     drawable: w::Drawable,
-    cool_time: f32,
+    config: config::Bullet,
 }
 
 impl System {
-    pub fn new(chan: mpsc::Receiver<Event>, ship: specs::Entity, drawable: w::Drawable)
+    pub fn new(chan: mpsc::Receiver<Event>, drawable: w::Drawable, config: config::Bullet)
                -> System
     {
         System {
             input: chan,
-            shoot: false,
-            ship_entity: ship,
+            guns: Vec::new(),
             drawable: drawable,
-            cool_time: 1.0,
+            config: config,
+        }
+    }
+
+    fn get_gun(&mut self, player: w::PlayerId) -> &mut Gun {
+        let index = player as usize;
+        if index >= self.guns.len() {
+            self.guns.resize(index + 1, Gun {
+                shoot: false,
+                cool_time: 1.0,
+            });
         }
+        &mut self.guns[index]
     }
 
     fn check_input(&mut self) {
         loop {
             match self.input.try_recv() {
-                Ok(Event::EvShoot(value)) => self.shoot = value,
+                Ok(Event::EvShoot(p, value)) => self.get_gun(p).shoot = value,
                 Err(_) => return,
             }
         }
     }
 
-    fn spawn(&self, w: &specs::World) -> specs::Entity {
-        let velocity = 5.0f32;
-        let s0 = {
-            let s = w.read::<w::Spatial>();
-            s.get(self.ship_entity).unwrap().clone()
-        };
-        let i0 = {
-            let s = w.read::<w::Inertial>();
-            s.get(self.ship_entity).unwrap().clone()
+    fn spawn(&mut self, w: &specs::World, time: super::Delta) {
+        use specs::Join;
+        let ships: Vec<_> = {
+            let (control, space, inertia) = (w.read::<w::Control>(),
+                w.read::<w::Spatial>(), w.read::<w::Inertial>());
+            (&control, &space, &inertia).iter()
+                .map(|(c, s, i)| (c.player, s.clone(), i.clone()))
+                .collect()
         };
+        for (player, s0, i0) in ships {
+            let cool_time = self.config.cool_time;
+            let ready = {
+                let gun = self.get_gun(player);
+                if gun.shoot && gun.cool_time == 0.0 {
+                    gun.cool_time += cool_time;
+                    true
+                } else {
+                    false
+                }
+            };
+            if ready {
+                self.spawn_from(w, player, s0, i0);
+            }
+        }
+        for gun in self.guns.iter_mut() {
+            gun.cool_time = (gun.cool_time - time).max(0.0);
+        }
+    }
+
+    fn spawn_from(&self, w: &specs::World, player: w::PlayerId, s0: w::Spatial, i0: w::Inertial)
+                  -> specs::Entity {
         w.create_later_build()
             .with(w::Bullet {
-                life_time: Some(1.0),
+                life_time: Some(self.config.life_time),
+                owner: player,
             })
             .with(self.drawable.clone())
             .with(w::Spatial {
@@ -61,13 +98,13 @@ impl System {
                 scale: 0.1,
             })
             .with(w::Inertial {
-                velocity: i0.velocity + s0.get_direction() * velocity,
+                velocity: i0.velocity + s0.get_direction() * self.config.speed,
                 angular_velocity: Rad{ s: 0.0 },
             })
             .with(w::Collision {
-                radius: 0.01,
-                health: 1,
-                damage: 1,
+                radius: self.config.radius,
+                health: self.config.health,
+                damage: self.config.damage,
             })
             .build()
     }
@@ -78,11 +115,7 @@ impl specs::System<super::Delta> for System {
         use specs::Join;
         self.check_input();
         let (mut bullet, entities) = arg.fetch(|w| {
-            if self.shoot && self.cool_time == 0.0 {
-                self.spawn(w);
-                self.cool_time += COOL_TIME;
-            }
-            self.cool_time = (self.cool_time - time).max(0.0);
+            self.spawn(w, time);
             (w.write::<w::Bullet>(), w.entities())
         });
         for (b, e) in (&mut bullet, &entities).iter() {
@@ -0,0 +1,53 @@
+use specs;
+use world as w;
+use super::inertia::wrap_coord;
+
+
+/// How fast the camera catches up with the ship, per second.
+const FOLLOW_RATE: f32 = 3.0;
+
+// the camera system moves the cameras smoothly after the ships they follow
+pub struct System {
+    wrap: bool,
+}
+
+impl System {
+    pub fn new(wrap: bool) -> System {
+        System {
+            wrap: wrap,
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        use specs::Join;
+        let (mut camera, control, space, screen) = arg.fetch(|w|
+            (w.write::<w::Camera>(), w.read::<w::Control>(), w.read::<w::Spatial>(),
+             *w.read_resource::<w::Screen>())
+        );
+        let ext = screen.level;
+        let factor = 1.0 - (-FOLLOW_RATE * time).exp();
+        for cam in (&mut camera).iter() {
+            let player = match cam.target {
+                Some(player) => player,
+                None => continue,
+            };
+            let target = match (&control, &space).iter().find(|&(c, _)| c.player == player) {
+                Some((_, s)) => s.pos,
+                None => continue,
+            };
+            let mut offset = target - cam.pos;
+            if self.wrap {
+                // go the short way across the edges
+                offset.x = wrap_coord(offset.x, ext[0]);
+                offset.y = wrap_coord(offset.y, ext[1]);
+            }
+            cam.pos = cam.pos + offset * factor;
+            if self.wrap {
+                cam.pos.x = wrap_coord(cam.pos.x, ext[0]);
+                cam.pos.y = wrap_coord(cam.pos.y, ext[1]);
+            }
+        }
+    }
+}
@@ -1,33 +1,50 @@
 use std::sync::mpsc;
-use cgmath::{Rad};
+use cgmath::{Rad, Vector2};
 use specs;
 use world as w;
 
+const DEBUG_INTENT_COLOR: u32 = 0xFF40FF00;
+/// Seconds of the intended acceleration shown by the debug line.
+const DEBUG_INTENT_TIME: f32 = 0.25;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Event {
-    EvThrust(f32),
-    EvTurn(f32),
+    EvThrust(w::PlayerId, f32),
+    EvTurn(w::PlayerId, f32),
 }
 
-pub struct System {
-    input: mpsc::Receiver<Event>,
+#[derive(Clone, Copy, Default)]
+struct Intent {
     thrust: f32,
     turn: f32,
 }
 
+pub struct System {
+    input: mpsc::Receiver<Event>,
+    intents: Vec<Intent>,
+}
+
 impl System {
     pub fn new(chan: mpsc::Receiver<Event>) -> System {
         System {
             input: chan,
-            thrust: 0.0,
-            turn: 0.0,
+            intents: Vec::new(),
         }
     }
 
+    fn get_intent(&mut self, player: w::PlayerId) -> &mut Intent {
+        let index = player as usize;
+        if index >= self.intents.len() {
+            self.intents.resize(index + 1, Intent::default());
+        }
+        &mut self.intents[index]
+    }
+
     fn check_input(&mut self) {
         loop {
             match self.input.try_recv() {
-                Ok(Event::EvThrust(v)) => self.thrust = v,
-                Ok(Event::EvTurn(v)) => self.turn = v,
+                Ok(Event::EvThrust(p, v)) => self.get_intent(p).thrust = v,
+                Ok(Event::EvTurn(p, v)) => self.get_intent(p).turn = v,
                 Err(_) => return,
             }
         }
@@ -38,15 +55,34 @@ impl specs::System<super::Delta> for System {
     fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
         use specs::Join;
         self.check_input();
-        let (mut inertia, space, control) = arg.fetch(|w|
-            (w.write::<w::Inertial>(), w.read::<w::Spatial>(), w.read::<w::Control>())
-        );
-        for (i, s, c) in (&mut inertia, &space, &control).iter() {
-            let rotate = c.turn_speed * self.turn;
+        let (mut inertia, mut emitter, space, control, mut debug) = arg.fetch(|w| {
+            let debug = if w.read_resource::<w::Effects>().debug {
+                Some(w.write_resource::<w::DebugShapes>())
+            } else {
+                None
+            };
+            (w.write::<w::Inertial>(), w.write::<w::Emitter>(),
+             w.read::<w::Spatial>(), w.read::<w::Control>(), debug)
+        });
+        for (i, em, s, c) in (&mut inertia, &mut emitter, &space, &control).iter() {
+            let intent = match self.intents.get(c.player as usize) {
+                Some(intent) => *intent,
+                None => Intent::default(),
+            };
+            let rotate = c.turn_speed * intent.turn;
             i.angular_velocity = Rad{ s: rotate };
             let dir = s.get_direction();
-            let velocity = time * c.thrust_speed * self.thrust;
+            let velocity = time * c.thrust_speed * intent.thrust;
             i.velocity = i.velocity + dir * velocity;
+            em.active = intent.thrust > 0.0;
+            if let Some(ref mut debug) = debug {
+                // the intended acceleration, and the turn to the side
+                let accel = dir * (c.thrust_speed * intent.thrust * DEBUG_INTENT_TIME);
+                let side = Vector2::new(dir.y, -dir.x) * (rotate * DEBUG_INTENT_TIME);
+                let (a, b) = (s.pos + accel, s.pos + side);
+                debug.0.push(w::DebugShape::Line([s.pos.x, s.pos.y], [a.x, a.y], DEBUG_INTENT_COLOR));
+                debug.0.push(w::DebugShape::Line([s.pos.x, s.pos.y], [b.x, b.y], DEBUG_INTENT_COLOR));
+            }
         }
     }
 }
@@ -0,0 +1,46 @@
+use super::draw::Vertex;
+
+
+/// Number of segments the circles are made of.
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// Something drawn on top of the game, for looking into the systems.
+/// Coordinates are in world units.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Line([f32; 2], [f32; 2], u32),
+    Circle([f32; 2], f32, u32),
+    /// The lower left and the upper right corners.
+    Rect([f32; 4], u32),
+}
+
+impl Shape {
+    /// Add the vertices of the shape, as pairs of line ends.
+    pub fn tessellate(&self, out: &mut Vec<Vertex>) {
+        match *self {
+            Shape::Line(a, b, color) => {
+                out.push(Vertex::new(a[0], a[1], color));
+                out.push(Vertex::new(b[0], b[1], color));
+            },
+            Shape::Circle(center, radius, color) => {
+                let point = |i: usize| {
+                    let angle = i as f32 * 2.0 * ::std::f32::consts::PI / CIRCLE_SEGMENTS as f32;
+                    Vertex::new(center[0] + radius * angle.cos(),
+                                center[1] + radius * angle.sin(), color)
+                };
+                for i in 0 .. CIRCLE_SEGMENTS {
+                    out.push(point(i));
+                    out.push(point(i + 1));
+                }
+            },
+            Shape::Rect(r, color) => {
+                let corners = [[r[0], r[1]], [r[2], r[1]], [r[2], r[3]], [r[0], r[3]]];
+                for i in 0 .. 4 {
+                    let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                    out.push(Vertex::new(a[0], a[1], color));
+                    out.push(Vertex::new(b[0], b[1], color));
+                }
+            },
+        }
+    }
+}
@@ -1,56 +1,170 @@
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{mpsc, Arc};
 
 use pegasus;
 use specs;
 use gfx;
 
+use config;
+use image::Image;
 use world;
+use super::{debug, font, stars};
+use super::inertia::wrap_coord;
 
 
 pub type ColorFormat = gfx::format::Srgba8;
+pub type DepthFormat = gfx::format::Depth;
 
 gfx_vertex_struct!( Vertex {
     pos: [f32; 2] = "a_Pos",
     color: [gfx::format::U8Norm; 4] = "a_Color",
 });
 
+fn unpack_color(col: u32) -> [gfx::format::U8Norm; 4] {
+    let c4 = [(col>>24) as u8, (col>>16) as u8, (col>>8) as u8, col as u8];
+    gfx::format::U8Norm::cast4(c4)
+}
+
 impl Vertex {
     pub fn new(x: f32, y: f32, col: u32) -> Vertex {
-        let c4 = [(col>>24) as u8, (col>>16) as u8, (col>>8) as u8, col as u8];
         Vertex {
             pos: [x, y],
-            color: gfx::format::U8Norm::cast4(c4),
+            color: unpack_color(col),
         }
     }
 }
 
+gfx_vertex_struct!( Instance {
+    transform: [f32; 4] = "a_Transform",
+    tint: [f32; 4] = "a_Tint",
+    depth: f32 = "a_Depth",
+    frame: f32 = "a_Frame",
+});
+
 gfx_constant_struct!(ShaderParam {
-    transform: [f32; 4] = "u_Transform",
-    screen_scale: [f32; 4] = "u_ScreenScale",
+    // camera position, rotation and zoom
+    view: [f32; 4] = "u_View",
+    // scale and offset from the view to the screen
+    screen: [f32; 4] = "u_Screen",
 });
 
 gfx_pipeline!(pipe {
+    vbuf: gfx::VertexBuffer<Vertex> = (),
+    instances: gfx::InstanceBuffer<Instance> = (),
+    param: gfx::ConstantBuffer<ShaderParam> = "c_Parameters",
+    scissor: gfx::Scissor = (),
+    output: gfx::BlendTarget<gfx::format::Srgba8> =
+        ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+    depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+});
+
+
+gfx_vertex_struct!( TextVertex {
+    pos: [f32; 2] = "a_Pos",
+    tex_coord: [f32; 2] = "a_TexCoord",
+    color: [gfx::format::U8Norm; 4] = "a_Color",
+});
+
+impl TextVertex {
+    fn new(x: f32, y: f32, u: f32, v: f32, col: u32) -> TextVertex {
+        TextVertex {
+            pos: [x, y],
+            tex_coord: [u, v],
+            color: unpack_color(col),
+        }
+    }
+}
+gfx_vertex_struct!( SpriteVertex {
+    pos: [f32; 2] = "a_Pos",
+    tex_coord: [f32; 2] = "a_TexCoord",
+});
+
+gfx_constant_struct!(SheetParam {
+    // number of the frame columns and rows
+    grid: [f32; 4] = "u_Grid",
+});
+
+gfx_pipeline!(sprite_pipe {
+    vbuf: gfx::VertexBuffer<SpriteVertex> = (),
+    instances: gfx::InstanceBuffer<Instance> = (),
+    param: gfx::ConstantBuffer<ShaderParam> = "c_Parameters",
+    sheet: gfx::ConstantBuffer<SheetParam> = "c_Sheet",
+    texture: gfx::TextureSampler<[f32; 4]> = "t_Sprite",
+    scissor: gfx::Scissor = (),
+    output: gfx::BlendTarget<gfx::format::Srgba8> =
+        ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+    depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+});
+
+
+gfx_pipeline!(text_pipe {
+    vbuf: gfx::VertexBuffer<TextVertex> = (),
+    font: gfx::TextureSampler<[f32; 4]> = "t_Font",
+    output: gfx::RenderTarget<gfx::format::Srgba8> = "Target0",
+});
+
+gfx_constant_struct!(StarParam {
+    // scroll of the layer, in screen sizes
+    offset: [f32; 4] = "u_Offset",
+});
+
+gfx_pipeline!(star_pipe {
+    vbuf: gfx::VertexBuffer<Vertex> = (),
+    param: gfx::ConstantBuffer<StarParam> = "c_Stars",
+    output: gfx::RenderTarget<gfx::format::Srgba8> = "Target0",
+});
+
+
+gfx_pipeline!(debug_pipe {
     vbuf: gfx::VertexBuffer<Vertex> = (),
     param: gfx::ConstantBuffer<ShaderParam> = "c_Parameters",
     output: gfx::RenderTarget<gfx::format::Srgba8> = "Target0",
 });
 
 
+gfx_vertex_struct!( PostVertex {
+    pos: [f32; 2] = "a_Pos",
+});
+
+gfx_constant_struct!(PostParam {
+    // persistence, strength and scanlines of the glow
+    effect: [f32; 4] = "u_Effect",
+    // distance between the blur samples, in texture coordinates
+    step: [f32; 4] = "u_Step",
+});
+
+gfx_pipeline!(post_pipe {
+    vbuf: gfx::VertexBuffer<PostVertex> = (),
+    param: gfx::ConstantBuffer<PostParam> = "c_Post",
+    source: gfx::TextureSampler<[f32; 4]> = "t_Source",
+    extra: gfx::TextureSampler<[f32; 4]> = "t_Extra",
+    output: gfx::RenderTarget<gfx::format::Srgba8> = "Target0",
+});
+
+
 const SHADER_VERT: &'static [u8] = b"
     #version 150 core
     in vec2 a_Pos;
     in vec4 a_Color;
+    in vec4 a_Transform;
+    in vec4 a_Tint;
+    in float a_Depth;
     uniform c_Parameters {
-        vec4 u_Transform;
-        vec4 u_ScreenScale;
+        vec4 u_View;
+        vec4 u_Screen;
     };
     out vec4 v_Color;
+    vec2 rotate(vec2 v, float angle) {
+        vec2 sc = vec2(sin(angle), cos(angle));
+        return vec2(v.x*sc.y - v.y*sc.x, v.x*sc.x + v.y*sc.y);
+    }
     void main() {
-        v_Color = a_Color;
-        vec2 sc = vec2(sin(u_Transform.z), cos(u_Transform.z));
-        vec2 p = vec2(a_Pos.x*sc.y - a_Pos.y*sc.x, a_Pos.x*sc.x + a_Pos.y*sc.y);
-        p = (p * u_Transform.w + u_Transform.xy) * u_ScreenScale.xy;
-        gl_Position = vec4(p, 0.0, 1.0);
+        v_Color = a_Color * a_Tint;
+        vec2 p = rotate(a_Pos, a_Transform.z) * a_Transform.w + a_Transform.xy;
+        p = rotate(p - u_View.xy, -u_View.z) * u_View.w;
+        gl_Position = vec4(p * u_Screen.xy + u_Screen.zw, a_Depth, 1.0);
     }
 ";
 const SHADER_FRAG: &'static [u8] = b"
@@ -61,46 +175,1022 @@ const SHADER_FRAG: &'static [u8] = b"
         Target0 = v_Color;
     }
 ";
+const SPRITE_VERT: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    in vec2 a_TexCoord;
+    in vec4 a_Transform;
+    in vec4 a_Tint;
+    in float a_Depth;
+    in float a_Frame;
+    uniform c_Parameters {
+        vec4 u_View;
+        vec4 u_Screen;
+    };
+    uniform c_Sheet {
+        vec4 u_Grid;
+    };
+    out vec2 v_TexCoord;
+    out vec4 v_Color;
+    vec2 rotate(vec2 v, float angle) {
+        vec2 sc = vec2(sin(angle), cos(angle));
+        return vec2(v.x*sc.y - v.y*sc.x, v.x*sc.x + v.y*sc.y);
+    }
+    void main() {
+        float column = mod(a_Frame, u_Grid.x);
+        float row = mod(floor(a_Frame / u_Grid.x), u_Grid.y);
+        v_TexCoord = (a_TexCoord + vec2(column, row)) / u_Grid.xy;
+        v_Color = a_Tint;
+        vec2 p = rotate(a_Pos, a_Transform.z) * a_Transform.w + a_Transform.xy;
+        p = rotate(p - u_View.xy, -u_View.z) * u_View.w;
+        gl_Position = vec4(p * u_Screen.xy + u_Screen.zw, a_Depth, 1.0);
+    }
+";
+const SPRITE_FRAG: &'static [u8] = b"
+    #version 150 core
+    uniform sampler2D t_Sprite;
+    in vec2 v_TexCoord;
+    in vec4 v_Color;
+    out vec4 Target0;
+    void main() {
+        vec4 texel = texture(t_Sprite, v_TexCoord);
+        // keep the depth of the transparent parts untouched
+        if (texel.a == 0.0)
+            discard;
+        Target0 = v_Color * texel;
+    }
+";
+const DEBUG_VERT: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    in vec4 a_Color;
+    uniform c_Parameters {
+        vec4 u_View;
+        vec4 u_Screen;
+    };
+    out vec4 v_Color;
+    vec2 rotate(vec2 v, float angle) {
+        vec2 sc = vec2(sin(angle), cos(angle));
+        return vec2(v.x*sc.y - v.y*sc.x, v.x*sc.x + v.y*sc.y);
+    }
+    void main() {
+        v_Color = a_Color;
+        vec2 p = rotate(a_Pos - u_View.xy, -u_View.z) * u_View.w;
+        gl_Position = vec4(p * u_Screen.xy + u_Screen.zw, 0.0, 1.0);
+    }
+";
+const STAR_VERT: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    in vec4 a_Color;
+    uniform c_Stars {
+        vec4 u_Offset;
+    };
+    out vec4 v_Color;
+    void main() {
+        v_Color = a_Color;
+        // the layer repeats itself in all directions
+        gl_Position = vec4(fract(a_Pos - u_Offset.xy) * 2.0 - 1.0, 0.0, 1.0);
+    }
+";
+const TEXT_VERT: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    in vec2 a_TexCoord;
+    in vec4 a_Color;
+    out vec2 v_TexCoord;
+    out vec4 v_Color;
+    void main() {
+        v_TexCoord = a_TexCoord;
+        v_Color = a_Color;
+        gl_Position = vec4(a_Pos, 0.0, 1.0);
+    }
+";
+const TEXT_FRAG: &'static [u8] = b"
+    #version 150 core
+    uniform sampler2D t_Font;
+    in vec2 v_TexCoord;
+    in vec4 v_Color;
+    out vec4 Target0;
+    void main() {
+        vec4 texel = texture(t_Font, v_TexCoord);
+        if (texel.a < 0.5)
+            discard;
+        Target0 = v_Color * texel;
+    }
+";
+const POST_VERT: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    out vec2 v_TexCoord;
+    void main() {
+        v_TexCoord = a_Pos * 0.5 + 0.5;
+        gl_Position = vec4(a_Pos, 0.0, 1.0);
+    }
+";
+const PERSIST_FRAG: &'static [u8] = b"
+    #version 150 core
+    uniform sampler2D t_Source;
+    uniform sampler2D t_Extra;
+    uniform c_Post {
+        vec4 u_Effect;
+        vec4 u_Step;
+    };
+    in vec2 v_TexCoord;
+    out vec4 Target0;
+    void main() {
+        // the phosphor keeps glowing after the beam is gone
+        vec4 fresh = texture(t_Source, v_TexCoord);
+        vec4 old = texture(t_Extra, v_TexCoord) * u_Effect.x;
+        Target0 = max(fresh, old);
+    }
+";
+const BLUR_FRAG: &'static [u8] = b"
+    #version 150 core
+    uniform sampler2D t_Source;
+    uniform c_Post {
+        vec4 u_Effect;
+        vec4 u_Step;
+    };
+    in vec2 v_TexCoord;
+    out vec4 Target0;
+    void main() {
+        const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+        vec4 sum = texture(t_Source, v_TexCoord) * weights[0];
+        for (int i = 1; i < 5; ++i) {
+            vec2 offset = u_Step.xy * float(i);
+            sum += (texture(t_Source, v_TexCoord + offset) +
+                texture(t_Source, v_TexCoord - offset)) * weights[i];
+        }
+        Target0 = sum;
+    }
+";
+const COMPOSITE_FRAG: &'static [u8] = b"
+    #version 150 core
+    uniform sampler2D t_Source;
+    uniform sampler2D t_Extra;
+    uniform c_Post {
+        vec4 u_Effect;
+        vec4 u_Step;
+    };
+    in vec2 v_TexCoord;
+    out vec4 Target0;
+    void main() {
+        vec4 color = texture(t_Source, v_TexCoord) + texture(t_Extra, v_TexCoord) * u_Effect.y;
+        // every other line of the screen is darker
+        float line = mod(floor(gl_FragCoord.y), 2.0);
+        Target0 = color * (1.0 - u_Effect.z * line);
+    }
+";
+
+
+/// Drawing order of the visuals, from the back to the front.
+/// The debug shapes and the text go over all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Background,
+    Asteroid,
+    Ship,
+    Bullet,
+    Particle,
+}
 
+const NUM_LAYERS: usize = 5;
+
+impl Default for Layer {
+    fn default() -> Layer {
+        Layer::Background
+    }
+}
+
+impl Layer {
+    /// Depth in the screen space, smaller being closer.
+    pub fn get_depth(&self) -> f32 {
+        1.0 - (*self as usize + 1) as f32 / (NUM_LAYERS + 1) as f32
+    }
+}
 
 #[derive(Clone)]
-pub struct Drawable(usize, ShaderParam);
+pub struct Drawable(usize, Instance);
+
+impl Drawable {
+    pub fn new(id: usize) -> Drawable {
+        Drawable(id, Instance {
+            transform: [0.0; 4],
+            tint: [1.0; 4],
+            depth: Layer::default().get_depth(),
+            frame: 0.0,
+        })
+    }
+
+    /// Put the visual in front of the lower layers.
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.1.depth = layer.get_depth();
+    }
+
+    /// Multiply the colors of the visual.
+    pub fn set_tint(&mut self, tint: [f32; 4]) {
+        self.1.tint = tint;
+    }
+
+    /// Show one of the sprite frames, counting row by row.
+    /// Has no effect on the visuals without frames.
+    pub fn set_frame(&mut self, frame: u32) {
+        self.1.frame = frame as f32;
+    }
+
+    /// Index of the visual, as returned by the painter.
+    pub fn get_visual(&self) -> usize {
+        self.0
+    }
+
+    /// A visual that doesn't refer to any GPU resources,
+    /// used when running the simulation without a window.
+    pub fn stub() -> Drawable {
+        Drawable::new(!0)
+    }
+}
 
 impl specs::Component for Drawable {
     type Storage = specs::VecStorage<Drawable>;
 }
 
+/// Image drawn on a quad, optionally cut into animation frames.
+#[derive(Clone, Debug)]
+pub struct Sprite {
+    /// Half-size of the quad, in the units of the visual.
+    pub extents: [f32; 2],
+    /// Number of the frame columns and rows in the image,
+    /// the frames going row by row from the top left corner.
+    pub grid: [u32; 2],
+    pub sampler: gfx::texture::SamplerInfo,
+}
+
+impl Sprite {
+    /// A single smoothly scaled frame.
+    pub fn new(extents: [f32; 2]) -> Sprite {
+        Sprite {
+            extents: extents,
+            grid: [1, 1],
+            sampler: gfx::texture::SamplerInfo::new(
+                gfx::texture::FilterMethod::Bilinear, gfx::texture::WrapMode::Clamp),
+        }
+    }
+
+    /// Cut the image into a sheet of frames of the same size.
+    pub fn frames(mut self, columns: u32, rows: u32) -> Sprite {
+        self.grid = [columns.max(1), rows.max(1)];
+        self
+    }
+
+    /// Use the given filtering and wrapping of the texture.
+    pub fn sampler(mut self, sampler: gfx::texture::SamplerInfo) -> Sprite {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn get_num_frames(&self) -> u32 {
+        self.grid[0] * self.grid[1]
+    }
+
+    /// Corners of the quad as a triangle strip, with the coordinates
+    /// within a single frame.
+    pub fn get_vertices(&self) -> [SpriteVertex; 4] {
+        let (x, y) = (self.extents[0], self.extents[1]);
+        [
+            SpriteVertex { pos: [-x, -y], tex_coord: [0.0, 1.0] },
+            SpriteVertex { pos: [x, -y], tex_coord: [1.0, 1.0] },
+            SpriteVertex { pos: [-x, y], tex_coord: [0.0, 0.0] },
+            SpriteVertex { pos: [x, y], tex_coord: [1.0, 0.0] },
+        ]
+    }
+}
+
+/// Radius of the circle enclosing any visual at unit scale.
+const VISUAL_RADIUS: f32 = 0.75;
+/// Number of instances uploaded for a single draw call.
+const MAX_INSTANCES: usize = 1024;
+
+/// Half-height of the minimap, in screen coordinates.
+const MINIMAP_SIZE: f32 = 0.2;
+/// Distance of the minimap from the screen corner.
+const MINIMAP_MARGIN: f32 = 0.05;
+
+/// How the world is seen, sent by the pre-draw system every frame.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub pos: [f32; 2],
+    pub rotation: f32,
+    pub zoom: f32,
+    /// Half-size of the level.
+    pub level: [f32; 2],
+    /// The level wraps around its edges.
+    pub wrap: bool,
+    /// Show the whole level in the corner.
+    pub minimap: bool,
+    /// The vector display look, if enabled.
+    pub glow: Option<config::Glow>,
+    /// How far the stars have scrolled, in world units.
+    pub scroll: [f32; 2],
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            pos: [0.0, 0.0],
+            rotation: 0.0,
+            zoom: 1.0,
+            level: [1.0, 1.0],
+            wrap: false,
+            minimap: false,
+            glow: None,
+            scroll: [0.0, 0.0],
+        }
+    }
+}
+
+/// Radius of the circle around the camera that covers the whole screen.
+fn get_view_radius(view: &View, extents: [f32; 2]) -> f32 {
+    (extents[0] * extents[0] + extents[1] * extents[1]).sqrt() / view.zoom
+}
+
+// add the instance at every place to draw it at: in the wrapped space,
+// that is every copy of it that may be seen by the camera
+fn push_copies(batch: &mut Vec<Instance>, inst: &Instance, view: &View, view_radius: f32) {
+    if !view.wrap {
+        batch.push(*inst);
+        return
+    }
+    let pos = [inst.transform[0], inst.transform[1]];
+    let radius = view_radius + inst.transform[3] * VISUAL_RADIUS;
+    for &kx in [-1.0, 0.0, 1.0].iter() {
+        for &ky in [-1.0, 0.0, 1.0].iter() {
+            let p = [pos[0] + 2.0 * kx * view.level[0], pos[1] + 2.0 * ky * view.level[1]];
+            let d = [p[0] - view.pos[0], p[1] - view.pos[1]];
+            if d[0] * d[0] + d[1] * d[1] < radius * radius {
+                let mut i = *inst;
+                i.transform[0] = p[0];
+                i.transform[1] = p[1];
+                batch.push(i);
+            }
+        }
+    }
+}
+
+/// Group the instances by visual, with a copy for every place they are seen at.
+/// The minimap batches get every instance once, when the view has a minimap.
+pub fn collect_batches<'a, I>(iter: I, batches: &mut [Vec<Instance>],
+                              minimap: &mut [Vec<Instance>], view: &View,
+                              extents: [f32; 2]) where
+    I: Iterator<Item = &'a Drawable>,
+{
+    let view_radius = get_view_radius(view, extents);
+    for batch in batches.iter_mut().chain(minimap.iter_mut()) {
+        batch.clear();
+    }
+    for &Drawable(vi, ref inst) in iter {
+        let batch = match batches.get_mut(vi) {
+            Some(batch) => batch,
+            None => continue,
+        };
+        push_copies(batch, inst, view, view_radius);
+        // the whole level is seen there, so nothing is culled or copied
+        if view.minimap {
+            if let Some(batch) = minimap.get_mut(vi) {
+                batch.push(*inst);
+            }
+        }
+    }
+}
+
+/// Shader parameters for looking through the camera at the whole screen.
+pub fn get_view_param(view: &View, extents: [f32; 2]) -> ShaderParam {
+    ShaderParam {
+        view: [view.pos[0], view.pos[1], view.rotation, view.zoom],
+        screen: [1.0 / extents[0], 1.0 / extents[1], 0.0, 0.0],
+    }
+}
+
+/// Shader parameters for showing the whole level in the bottom right corner,
+/// together with the covered rectangle, as the lower left and upper right
+/// corners in screen coordinates.
+pub fn get_minimap_param(view: &View, extents: [f32; 2]) -> (ShaderParam, [f32; 4]) {
+    let half = [MINIMAP_SIZE * view.level[0] / view.level[1] * extents[1] / extents[0],
+                MINIMAP_SIZE];
+    let center = [1.0 - MINIMAP_MARGIN - half[0], MINIMAP_MARGIN + half[1] - 1.0];
+    let param = ShaderParam {
+        view: [0.0, 0.0, 0.0, 1.0],
+        screen: [half[0] / view.level[0], half[1] / view.level[1], center[0], center[1]],
+    };
+    let rect = [center[0] - half[0], center[1] - half[1],
+                center[0] + half[0], center[1] + half[1]];
+    (param, rect)
+}
+
+// a visual is normally one draw call, unless it has more
+// instances than fit into the buffer
+fn draw_instances<R, C, D>(slice: &gfx::Slice<R>, pso: &gfx::PipelineState<R, D::Meta>, data: &D,
+                           instances: &gfx::handle::Buffer<R, Instance>, batch: &[Instance],
+                           encoder: &mut gfx::Encoder<R, C>) where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    D: gfx::pso::PipelineData<R>,
+{
+    for chunk in batch.chunks(MAX_INSTANCES) {
+        encoder.update_buffer(instances, chunk, 0).unwrap();
+        let mut slice = slice.clone();
+        slice.instances = Some((chunk.len() as gfx::InstanceCount, 0));
+        encoder.draw(&slice, pso, data);
+    }
+}
+
+fn encode_batches<R, C>(bundles: &mut [VisualBundle<R>], batches: &[Vec<Instance>],
+                        param: &ShaderParam, scissor: gfx::Rect,
+                        output: &gfx::handle::RenderTargetView<R, ColorFormat>,
+                        depth: &gfx::handle::DepthStencilView<R, DepthFormat>,
+                        encoder: &mut gfx::Encoder<R, C>) where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+{
+    // the depth test doesn't let the translucent visuals show what's behind them,
+    // unless it's drawn before, so the visuals go from the back to the front
+    let mut order: Vec<_> = (0 .. bundles.len().min(batches.len()))
+        .filter(|&i| !batches[i].is_empty())
+        .collect();
+    order.sort_by(|&a, &b| batches[b][0].depth.partial_cmp(&batches[a][0].depth)
+        .unwrap_or(Ordering::Equal));
+    for i in order {
+        let batch = &batches[i];
+        match bundles[i] {
+            VisualBundle::Shape(ref mut b) => {
+                b.data.scissor = scissor;
+                b.data.output = output.clone();
+                b.data.depth = depth.clone();
+                encoder.update_constant_buffer(&b.data.param, param);
+                draw_instances(&b.slice, &*b.pso, &b.data, &b.data.instances, batch, encoder);
+            },
+            VisualBundle::Sprite(ref mut b, ref sheet) => {
+                b.data.scissor = scissor;
+                b.data.output = output.clone();
+                b.data.depth = depth.clone();
+                encoder.update_constant_buffer(&b.data.param, param);
+                encoder.update_constant_buffer(&b.data.sheet, sheet);
+                draw_instances(&b.slice, &*b.pso, &b.data, &b.data.instances, batch, encoder);
+            },
+        }
+    }
+}
+
+/// Number of text vertices drawn in a frame, six per character.
+const MAX_TEXT_VERTICES: usize = 6 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A line of text drawn on top of everything else.
+#[derive(Clone, Debug)]
+pub struct Text {
+    /// Anchor on the top of the line, in screen coordinates from -1 to 1.
+    pub pos: [f32; 2],
+    /// Height of the characters, in screen coordinates.
+    pub size: f32,
+    /// Which side of the line the anchor is at.
+    pub align: Align,
+    pub color: u32,
+    pub text: String,
+}
+
+// two triangles per character, with the glyphs taken from the font atlas,
+// keeping their proportions on the screen of the given aspect ratio
+pub fn layout_text(texts: &[Text], aspect: f32) -> Vec<TextVertex> {
+    let mut vertices = Vec::new();
+    for t in texts {
+        let advance = t.size * font::CELL_SIZE[0] as f32 / font::CELL_SIZE[1] as f32 / aspect;
+        let width = advance * t.text.chars().count() as f32;
+        let x0 = match t.align {
+            Align::Left => t.pos[0],
+            Align::Center => t.pos[0] - 0.5 * width,
+            Align::Right => t.pos[0] - width,
+        };
+        for (i, c) in t.text.chars().enumerate() {
+            let r = match font::glyph_index(c) {
+                Some(index) => font::glyph_rect(index),
+                None => continue,
+            };
+            let (x, y) = (x0 + i as f32 * advance, t.pos[1]);
+            let corners = [
+                TextVertex::new(x, y, r[0], r[1], t.color),
+                TextVertex::new(x + advance, y, r[2], r[1], t.color),
+                TextVertex::new(x, y - t.size, r[0], r[3], t.color),
+                TextVertex::new(x + advance, y - t.size, r[2], r[3], t.color),
+            ];
+            for &k in [0, 1, 2, 2, 1, 3].iter() {
+                vertices.push(corners[k]);
+            }
+        }
+    }
+    vertices
+}
+
+struct TextLayer<R: gfx::Resources> {
+    pso: gfx::PipelineState<R, text_pipe::Meta>,
+    data: text_pipe::Data<R>,
+    input: mpsc::Receiver<Vec<Text>>,
+    output: mpsc::Sender<Vec<Text>>,
+    /// The latest texts received.
+    texts: Vec<Text>,
+}
+
+/// The main color and depth targets, together with their size in pixels,
+/// and the offscreen targets of the glow made for the same size.
+pub type Target<R> = (gfx::handle::RenderTargetView<R, ColorFormat>,
+                      gfx::handle::DepthStencilView<R, DepthFormat>, [u32; 2],
+                      Option<PostTargets<R>>);
+
+/// Shader code, as the vertex and fragment parts.
+type ShaderSource = (&'static [u8], &'static [u8]);
+type PsoKey = (ShaderSource, gfx::Primitive, gfx::state::Rasterizer);
+type Pso<R> = gfx::PipelineState<R, pipe::Meta>;
+
+// same as `gfx::Bundle`, but allows the pipeline state to be shared
+struct Bundle<R: gfx::Resources, M, D> {
+    slice: gfx::Slice<R>,
+    pso: Arc<gfx::PipelineState<R, M>>,
+    data: D,
+}
+
+enum VisualBundle<R: gfx::Resources> {
+    /// Colored by the vertices.
+    Shape(Bundle<R, pipe::Meta, pipe::Data<R>>),
+    /// Textured, together with the layout of its frames.
+    Sprite(Bundle<R, sprite_pipe::Meta, sprite_pipe::Data<R>>, SheetParam),
+}
+
+/// Number of line vertices drawn in a frame, two per segment.
+const MAX_DEBUG_VERTICES: usize = 2 * 8192;
+
+struct DebugLayer<R: gfx::Resources> {
+    pso: gfx::PipelineState<R, debug_pipe::Meta>,
+    data: debug_pipe::Data<R>,
+    input: mpsc::Receiver<Vec<debug::Shape>>,
+    output: mpsc::Sender<Vec<debug::Shape>>,
+    /// The latest shapes received.
+    shapes: Vec<debug::Shape>,
+}
+
+struct StarLayer<R: gfx::Resources> {
+    parallax: f32,
+    slice: gfx::Slice<R>,
+    data: star_pipe::Data<R>,
+}
+
+// the stars are drawn behind everything, one call per layer
+struct Background<R: gfx::Resources> {
+    pso: gfx::PipelineState<R, star_pipe::Meta>,
+    layers: Vec<StarLayer<R>>,
+}
+
+/// Size of the offscreen targets, if the screen size is not known.
+const DEFAULT_POST_SIZE: [u32; 2] = [800, 600];
+
+struct PostTarget<R: gfx::Resources> {
+    view: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    target: gfx::handle::RenderTargetView<R, ColorFormat>,
+}
+
+fn make_post_target<R, F>(factory: &mut F, size: [u32; 2]) -> PostTarget<R> where
+    R: gfx::Resources,
+    F: gfx::Factory<R>,
+{
+    let (_, view, target) = factory.create_render_target::<ColorFormat>(
+        size[0] as gfx::texture::Size, size[1] as gfx::texture::Size).unwrap();
+    PostTarget {
+        view: view,
+        target: target,
+    }
+}
+
+/// Offscreen targets of the glow, matching the screen size.
+/// The painter can't create them after it's started, so they are sent to it
+/// together with the main targets of a resized window.
+pub struct PostTargets<R: gfx::Resources> {
+    /// Size of the targets, in pixels.
+    size: [u32; 2],
+    scene: PostTarget<R>,
+    scene_depth: gfx::handle::DepthStencilView<R, DepthFormat>,
+    history: [PostTarget<R>; 2],
+    blur: [PostTarget<R>; 2],
+}
+
+impl<R: gfx::Resources> PostTargets<R> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, size: [u32; 2]) -> PostTargets<R> {
+        let size = [size[0].max(1), size[1].max(1)];
+        let half = [(size[0] / 2).max(1), (size[1] / 2).max(1)];
+        PostTargets {
+            size: size,
+            scene: make_post_target(factory, size),
+            scene_depth: factory.create_depth_stencil_view_only(
+                size[0] as gfx::texture::Size, size[1] as gfx::texture::Size).unwrap(),
+            history: [make_post_target(factory, size), make_post_target(factory, size)],
+            blur: [make_post_target(factory, half), make_post_target(factory, half)],
+        }
+    }
+}
+
+// the scene is drawn offscreen, then the last frames are kept fading in the history,
+// which is blurred at half the size and added on top of itself with the scanlines
+struct PostLayer<R: gfx::Resources> {
+    targets: PostTargets<R>,
+    /// Index of the history holding the last frame.
+    current: usize,
+    /// Was the effect enabled on the last frame.
+    active: bool,
+    persist: gfx::PipelineState<R, post_pipe::Meta>,
+    blur_pso: gfx::PipelineState<R, post_pipe::Meta>,
+    composite: gfx::PipelineState<R, post_pipe::Meta>,
+    vbuf: gfx::handle::Buffer<R, PostVertex>,
+    slice: gfx::Slice<R>,
+    param: gfx::handle::Buffer<R, PostParam>,
+    sampler: gfx::handle::Sampler<R>,
+}
+
+impl<R: gfx::Resources> PostLayer<R> {
+    fn pass<C>(&self, pso: &gfx::PipelineState<R, post_pipe::Meta>, source: &PostTarget<R>,
+               extra: &PostTarget<R>, output: &gfx::handle::RenderTargetView<R, ColorFormat>,
+               param: PostParam, encoder: &mut gfx::Encoder<R, C>) where
+        C: gfx::CommandBuffer<R>,
+    {
+        encoder.update_constant_buffer(&self.param, &param);
+        let data = post_pipe::Data {
+            vbuf: self.vbuf.clone(),
+            param: self.param.clone(),
+            source: (source.view.clone(), self.sampler.clone()),
+            extra: (extra.view.clone(), self.sampler.clone()),
+            output: output.clone(),
+        };
+        encoder.draw(&self.slice, pso, &data);
+    }
+
+    fn apply<C>(&mut self, glow: &config::Glow,
+                output: &gfx::handle::RenderTargetView<R, ColorFormat>,
+                encoder: &mut gfx::Encoder<R, C>) where
+        C: gfx::CommandBuffer<R>,
+    {
+        let t = &self.targets;
+        if !self.active {
+            // forget the frames from the last time the effect was on
+            for h in t.history.iter() {
+                encoder.clear(&h.target, [0.0, 0.0, 0.0, 1.0]);
+            }
+            self.active = true;
+        }
+        let (last, next) = (self.current, 1 - self.current);
+        self.current = next;
+        let effect = [glow.persistence, glow.strength, glow.scanlines, 0.0];
+        let texel = [2.0 / t.size[0] as f32, 2.0 / t.size[1] as f32];
+        self.pass(&self.persist, &t.scene, &t.history[last], &t.history[next].target,
+            PostParam { effect: effect, step: [0.0; 4] }, encoder);
+        self.pass(&self.blur_pso, &t.history[next], &t.history[next], &t.blur[0].target,
+            PostParam { effect: effect, step: [texel[0], 0.0, 0.0, 0.0] }, encoder);
+        self.pass(&self.blur_pso, &t.blur[0], &t.blur[0], &t.blur[1].target,
+            PostParam { effect: effect, step: [0.0, texel[1], 0.0, 0.0] }, encoder);
+        self.pass(&self.composite, &t.history[next], &t.blur[1], output,
+            PostParam { effect: effect, step: [0.0; 4] }, encoder);
+    }
+}
+
 pub struct Painter<R: gfx::Resources> {
     out_color: gfx::handle::RenderTargetView<R, ColorFormat>,
-    bundles: Arc<Vec<gfx::Bundle<R, pipe::Data<R>>>>,
+    out_depth: gfx::handle::DepthStencilView<R, DepthFormat>,
+    programs: HashMap<ShaderSource, gfx::handle::Program<R>>,
+    psos: HashMap<PsoKey, Arc<Pso<R>>>,
+    sprite_pso: Option<Arc<gfx::PipelineState<R, sprite_pipe::Meta>>>,
+    bundles: Vec<VisualBundle<R>>,
+    /// Instances collected for each visual during a frame.
+    batches: Vec<Vec<Instance>>,
+    minimap: Vec<Vec<Instance>>,
+    text: Option<TextLayer<R>>,
+    background: Option<Background<R>>,
+    debug: Option<DebugLayer<R>>,
+    post: Option<PostLayer<R>>,
+    target_input: mpsc::Receiver<Target<R>>,
+    target_output: mpsc::Sender<Target<R>>,
+    view_input: mpsc::Receiver<View>,
+    view_output: mpsc::Sender<View>,
+    view: View,
+    /// The screen size in pixels, once known.
+    size: Option<[u32; 2]>,
+    extents: [f32; 2],
 }
 
 impl<R: gfx::Resources> Painter<R> {
-    pub fn new(target: gfx::handle::RenderTargetView<R, ColorFormat>) -> Painter<R> {
+    pub fn new(color: gfx::handle::RenderTargetView<R, ColorFormat>,
+               depth: gfx::handle::DepthStencilView<R, DepthFormat>) -> Painter<R> {
+        let (target_output, target_input) = mpsc::channel();
+        let (view_output, view_input) = mpsc::channel();
         Painter {
-            out_color: target,
-            bundles: Arc::new(Vec::new()),
+            out_color: color,
+            out_depth: depth,
+            programs: HashMap::new(),
+            psos: HashMap::new(),
+            sprite_pso: None,
+            bundles: Vec::new(),
+            batches: Vec::new(),
+            minimap: Vec::new(),
+            text: None,
+            background: None,
+            debug: None,
+            post: None,
+            target_input: target_input,
+            target_output: target_output,
+            view_input: view_input,
+            view_output: view_output,
+            view: View::default(),
+            size: None,
+            extents: [1.0, 1.0],
         }
     }
 
+    /// Set the half-size of the visible area, in world units.
+    /// Its width is adjusted to the aspect ratio of the screen.
+    pub fn set_extents(&mut self, extents: [f32; 2]) {
+        self.extents = extents;
+    }
+
+    /// Switch to new main targets, for example after the window is resized.
+    pub fn set_target(&mut self, target: Target<R>) {
+        let (color, depth, size, post_targets) = target;
+        if let Some(ref mut layer) = self.text {
+            layer.data.output = color.clone();
+        }
+        if let (Some(post), Some(targets)) = (self.post.as_mut(), post_targets) {
+            post.targets = targets;
+            // the history is gone with the old targets
+            post.active = false;
+        }
+        self.out_color = color;
+        self.out_depth = depth;
+        self.size = Some(size);
+    }
+
+    /// Get the channel to send new targets to while the painter is running.
+    pub fn get_target_sender(&self) -> mpsc::Sender<Target<R>> {
+        self.target_output.clone()
+    }
+
+    /// Get the channel to send the camera views to.
+    pub fn get_view_sender(&self) -> mpsc::Sender<View> {
+        self.view_output.clone()
+    }
+
+    fn get_screen(&self) -> world::Screen {
+        let screen = world::Screen::new(self.extents, 1.0);
+        match self.size {
+            Some(size) => screen.fit(size),
+            None => screen,
+        }
+    }
+
+    fn get_program<F: gfx::Factory<R>>(&mut self, factory: &mut F, shaders: ShaderSource)
+                   -> &gfx::handle::Program<R> {
+        use gfx::traits::FactoryExt;
+        self.programs.entry(shaders).or_insert_with(||
+            factory.link_program(shaders.0, shaders.1).unwrap()
+        )
+    }
+
+    // visuals sharing the shaders and the states use the same program and pipeline
+    fn get_pso<F: gfx::Factory<R>>(&mut self, factory: &mut F, shaders: ShaderSource,
+               primitive: gfx::Primitive, rast: gfx::state::Rasterizer) -> Arc<Pso<R>> {
+        use gfx::traits::FactoryExt;
+        let key = (shaders, primitive, rast);
+        if let Some(pso) = self.psos.get(&key) {
+            return pso.clone()
+        }
+        let pso = {
+            let program = self.get_program(factory, shaders);
+            Arc::new(factory.create_pipeline_from_program(
+                program, primitive, rast, pipe::new()).unwrap())
+        };
+        self.psos.insert(key, pso.clone());
+        pso
+    }
+
     pub fn add_visual<F: gfx::Factory<R>>(&mut self, factory: &mut F, primitive: gfx::Primitive,
                       rast: gfx::state::Rasterizer, vertices: &[Vertex]) -> Drawable {
         use gfx::traits::FactoryExt;
-        let program = factory.link_program(SHADER_VERT, SHADER_FRAG).unwrap();
-        let pso = factory.create_pipeline_from_program(&program, primitive, rast, pipe::new()).unwrap();
+        use gfx::{buffer, memory};
+        let pso = self.get_pso(factory, (SHADER_VERT, SHADER_FRAG), primitive, rast);
         let (vbuf, slice) = factory.create_vertex_buffer_with_slice(vertices, ());
         let data = pipe::Data {
             vbuf: vbuf,
+            instances: factory.create_buffer(MAX_INSTANCES, buffer::Role::Vertex,
+                memory::Usage::Dynamic, gfx::Bind::empty()).unwrap(),
             param: factory.create_constant_buffer(1),
+            scissor: gfx::Rect { x: 0, y: 0, w: !0, h: !0 },
             output: self.out_color.clone(),
+            depth: self.out_depth.clone(),
         };
         let id = self.bundles.len();
-        let mut bundles = Arc::get_mut(&mut self.bundles).unwrap();
-        bundles.push(gfx::Bundle::new(slice, pso, data));
-        Drawable(id, ShaderParam {
-            transform: [0.0; 4],
-            screen_scale: [0.0; 4],
-        })
+        self.bundles.push(VisualBundle::Shape(Bundle {
+            slice: slice,
+            pso: pso,
+            data: data,
+        }));
+        self.batches.push(Vec::new());
+        self.minimap.push(Vec::new());
+        Drawable::new(id)
+    }
+
+    /// Add a visual showing the image, usually loaded from a PNG file.
+    /// Like the other visuals, it should fit into the unit circle at scale one.
+    pub fn add_sprite<F: gfx::Factory<R>>(&mut self, factory: &mut F, image: &Image,
+                      sprite: &Sprite) -> Drawable {
+        use gfx::traits::FactoryExt;
+        use gfx::{buffer, memory, texture};
+        let pso = match self.sprite_pso {
+            Some(ref pso) => pso.clone(),
+            None => {
+                let pso = {
+                    let program = self.get_program(factory, (SPRITE_VERT, SPRITE_FRAG));
+                    Arc::new(factory.create_pipeline_from_program(program,
+                        gfx::Primitive::TriangleStrip, gfx::state::Rasterizer::new_fill(),
+                        sprite_pipe::new()).unwrap())
+                };
+                self.sprite_pso = Some(pso.clone());
+                pso
+            },
+        };
+        let kind = texture::Kind::D2(image.size[0] as texture::Size,
+            image.size[1] as texture::Size, texture::AaMode::Single);
+        let (_, view) = factory.create_texture_immutable::<ColorFormat>(
+            kind, &[&image.pixels]).unwrap();
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&sprite.get_vertices(), ());
+        let data = sprite_pipe::Data {
+            vbuf: vbuf,
+            instances: factory.create_buffer(MAX_INSTANCES, buffer::Role::Vertex,
+                memory::Usage::Dynamic, gfx::Bind::empty()).unwrap(),
+            param: factory.create_constant_buffer(1),
+            sheet: factory.create_constant_buffer(1),
+            texture: (view, factory.create_sampler(sprite.sampler)),
+            scissor: gfx::Rect { x: 0, y: 0, w: !0, h: !0 },
+            output: self.out_color.clone(),
+            depth: self.out_depth.clone(),
+        };
+        let sheet = SheetParam {
+            grid: [sprite.grid[0] as f32, sprite.grid[1] as f32, 0.0, 0.0],
+        };
+        let id = self.bundles.len();
+        self.bundles.push(VisualBundle::Sprite(Bundle {
+            slice: slice,
+            pso: pso,
+            data: data,
+        }, sheet));
+        self.batches.push(Vec::new());
+        self.minimap.push(Vec::new());
+        Drawable::new(id)
+    }
+
+    /// Prepare the text drawing, returning the channel to send the lines to draw.
+    /// Each set of lines sent replaces the previous one.
+    pub fn add_text<F: gfx::Factory<R>>(&mut self, factory: &mut F) -> mpsc::Sender<Vec<Text>> {
+        use gfx::traits::FactoryExt;
+        use gfx::{buffer, memory, texture};
+        if let Some(ref layer) = self.text {
+            return layer.output.clone()
+        }
+        let pso = {
+            let program = self.get_program(factory, (TEXT_VERT, TEXT_FRAG));
+            factory.create_pipeline_from_program(program, gfx::Primitive::TriangleList,
+                gfx::state::Rasterizer::new_fill(), text_pipe::new()).unwrap()
+        };
+        let size = font::atlas_size();
+        let kind = texture::Kind::D2(size[0] as texture::Size, size[1] as texture::Size,
+            texture::AaMode::Single);
+        let (_, view) = factory.create_texture_immutable::<gfx::format::Rgba8>(
+            kind, &[&font::make_atlas()]).unwrap();
+        let sampler = factory.create_sampler(texture::SamplerInfo::new(
+            texture::FilterMethod::Scale, texture::WrapMode::Clamp));
+        let data = text_pipe::Data {
+            vbuf: factory.create_buffer(MAX_TEXT_VERTICES, buffer::Role::Vertex,
+                memory::Usage::Dynamic, gfx::Bind::empty()).unwrap(),
+            font: (view, sampler),
+            output: self.out_color.clone(),
+        };
+        let (output, input) = mpsc::channel();
+        self.text = Some(TextLayer {
+            pso: pso,
+            data: data,
+            input: input,
+            output: output.clone(),
+            texts: Vec::new(),
+        });
+        output
+    }
+
+    /// Prepare the debug drawing, returning the channel to send the shapes to.
+    /// Each set of shapes sent replaces the previous one.
+    pub fn add_debug<F: gfx::Factory<R>>(&mut self, factory: &mut F)
+                     -> mpsc::Sender<Vec<debug::Shape>> {
+        use gfx::traits::FactoryExt;
+        use gfx::{buffer, memory};
+        if let Some(ref layer) = self.debug {
+            return layer.output.clone()
+        }
+        let pso = {
+            let program = self.get_program(factory, (DEBUG_VERT, SHADER_FRAG));
+            factory.create_pipeline_from_program(program, gfx::Primitive::LineList,
+                gfx::state::Rasterizer::new_fill(), debug_pipe::new()).unwrap()
+        };
+        let data = debug_pipe::Data {
+            vbuf: factory.create_buffer(MAX_DEBUG_VERTICES, buffer::Role::Vertex,
+                memory::Usage::Dynamic, gfx::Bind::empty()).unwrap(),
+            param: factory.create_constant_buffer(1),
+            output: self.out_color.clone(),
+        };
+        let (output, input) = mpsc::channel();
+        self.debug = Some(DebugLayer {
+            pso: pso,
+            data: data,
+            input: input,
+            output: output.clone(),
+            shapes: Vec::new(),
+        });
+        output
+    }
+
+    /// Prepare the starfield in the background, placed after the seed.
+    pub fn add_stars<F: gfx::Factory<R>>(&mut self, factory: &mut F, seed: usize) {
+        use gfx::traits::FactoryExt;
+        let pso = {
+            let program = self.get_program(factory, (STAR_VERT, SHADER_FRAG));
+            factory.create_pipeline_from_program(program, gfx::Primitive::PointList,
+                gfx::state::Rasterizer::new_fill(), star_pipe::new()).unwrap()
+        };
+        let layers = stars::make_layers(seed).into_iter().map(|layer| {
+            let vertices: Vec<_> = layer.stars.iter()
+                .map(|s| Vertex::new(s[0], s[1], layer.color))
+                .collect();
+            let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertices, ());
+            StarLayer {
+                parallax: layer.parallax,
+                slice: slice,
+                data: star_pipe::Data {
+                    vbuf: vbuf,
+                    param: factory.create_constant_buffer(1),
+                    output: self.out_color.clone(),
+                },
+            }
+        }).collect();
+        self.background = Some(Background {
+            pso: pso,
+            layers: layers,
+        });
+    }
+
+    // all the post-processing passes draw a screen quad
+    fn get_post_pso<F: gfx::Factory<R>>(&mut self, factory: &mut F, frag: &'static [u8])
+                    -> gfx::PipelineState<R, post_pipe::Meta> {
+        use gfx::traits::FactoryExt;
+        let program = self.get_program(factory, (POST_VERT, frag));
+        factory.create_pipeline_from_program(program, gfx::Primitive::TriangleStrip,
+            gfx::state::Rasterizer::new_fill(), post_pipe::new()).unwrap()
+    }
+
+    /// Prepare the post-processing for the vector display look,
+    /// drawing offscreen at the current screen size.
+    pub fn add_glow<F: gfx::Factory<R>>(&mut self, factory: &mut F) {
+        use gfx::traits::FactoryExt;
+        if self.post.is_some() {
+            return
+        }
+        let persist = self.get_post_pso(factory, PERSIST_FRAG);
+        let blur_pso = self.get_post_pso(factory, BLUR_FRAG);
+        let composite = self.get_post_pso(factory, COMPOSITE_FRAG);
+        let size = self.size.unwrap_or(DEFAULT_POST_SIZE);
+        let vertices = [
+            PostVertex { pos: [-1.0, -1.0] },
+            PostVertex { pos: [1.0, -1.0] },
+            PostVertex { pos: [-1.0, 1.0] },
+            PostVertex { pos: [1.0, 1.0] },
+        ];
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertices, ());
+        self.post = Some(PostLayer {
+            targets: PostTargets::new(factory, size),
+            current: 0,
+            active: false,
+            persist: persist,
+            blur_pso: blur_pso,
+            composite: composite,
+            vbuf: vbuf,
+            slice: slice,
+            param: factory.create_constant_buffer(1),
+            sampler: factory.create_sampler_linear(),
+        });
     }
 }
 
@@ -110,40 +1200,195 @@ impl<R: gfx::Resources> pegasus::Painter<R> for Painter<R> {
         I: Iterator<Item = &'a Self::Visual>,
         C: gfx::CommandBuffer<R>
     {
-        encoder.clear(&self.out_color, [0.0, 0.0, 0.0, 1.0]);
-        for &Drawable(vi, ref param) in iter {
-            let b = &self.bundles[vi];
-            encoder.update_constant_buffer(&b.data.param, param);
-            b.encode(encoder);
+        while let Ok(target) = self.target_input.try_recv() {
+            self.set_target(target);
+        }
+        while let Ok(view) = self.view_input.try_recv() {
+            self.view = view;
+        }
+        let extents = self.get_screen().extents;
+        let view = self.view;
+        let glow = match self.post {
+            Some(ref mut post) => {
+                post.active &= view.glow.is_some();
+                view.glow
+            },
+            None => None,
+        };
+        // the scene goes offscreen when it's post-processed
+        let (scene, depth, size) = match (glow, self.post.as_ref()) {
+            (Some(_), Some(post)) => (post.targets.scene.target.clone(),
+                post.targets.scene_depth.clone(), post.targets.size),
+            _ => (self.out_color.clone(), self.out_depth.clone(), self.size.unwrap_or([!0, !0])),
+        };
+        encoder.clear(&scene, [0.0, 0.0, 0.0, 1.0]);
+        encoder.clear_depth(&depth, 1.0);
+        if let Some(ref mut bg) = self.background {
+            for layer in bg.layers.iter_mut() {
+                let offset = stars::get_offset(layer.parallax, view.scroll, extents);
+                encoder.update_constant_buffer(&layer.data.param, &StarParam {
+                    offset: [offset[0], offset[1], 0.0, 0.0],
+                });
+                layer.data.output = scene.clone();
+                encoder.draw(&layer.slice, &bg.pso, &layer.data);
+            }
+        }
+        collect_batches(iter, &mut self.batches, &mut self.minimap, &view, extents);
+        let param = get_view_param(&view, extents);
+        let full = gfx::Rect { x: 0, y: 0, w: size[0] as u16, h: size[1] as u16 };
+        encode_batches(&mut self.bundles, &self.batches, &param, full, &scene, &depth, encoder);
+        if view.minimap {
+            let (param, r) = get_minimap_param(&view, extents);
+            let to_pixels = |v: f32, i: usize| (0.5 * v * size[i] as f32) as u16;
+            let rect = gfx::Rect {
+                x: to_pixels(r[0] + 1.0, 0),
+                y: to_pixels(r[1] + 1.0, 1),
+                w: to_pixels(r[2] - r[0], 0),
+                h: to_pixels(r[3] - r[1], 1),
+            };
+            // the main view is done with the depth, so it's cleared
+            // for the minimap to go on top of everything
+            encoder.clear_depth(&depth, 1.0);
+            encode_batches(&mut self.bundles, &self.minimap, &param, rect, &scene, &depth, encoder);
+        }
+        if let (Some(glow), Some(post)) = (glow, self.post.as_mut()) {
+            post.apply(&glow, &self.out_color, encoder);
+        }
+        // the debug shapes are not post-processed, to stay sharp
+        if let Some(ref mut layer) = self.debug {
+            while let Ok(shapes) = layer.input.try_recv() {
+                layer.shapes = shapes;
+            }
+            let mut vertices = Vec::new();
+            for shape in layer.shapes.iter() {
+                shape.tessellate(&mut vertices);
+            }
+            vertices.truncate(MAX_DEBUG_VERTICES);
+            if !vertices.is_empty() {
+                encoder.update_buffer(&layer.data.vbuf, &vertices, 0).unwrap();
+                encoder.update_constant_buffer(&layer.data.param, &get_view_param(&view, extents));
+                layer.data.output = self.out_color.clone();
+                let slice = gfx::Slice {
+                    start: 0,
+                    end: vertices.len() as gfx::VertexCount,
+                    base_vertex: 0,
+                    instances: None,
+                    buffer: gfx::IndexBuffer::Auto,
+                };
+                encoder.draw(&slice, &layer.pso, &layer.data);
+            }
+        }
+        if let Some(ref mut layer) = self.text {
+            while let Ok(texts) = layer.input.try_recv() {
+                layer.texts = texts;
+            }
+            let aspect = match self.size {
+                Some(size) => size[0] as f32 / size[1].max(1) as f32,
+                None => 1.0,
+            };
+            let mut vertices = layout_text(&layer.texts, aspect);
+            vertices.truncate(MAX_TEXT_VERTICES);
+            if !vertices.is_empty() {
+                encoder.update_buffer(&layer.data.vbuf, &vertices, 0).unwrap();
+                let slice = gfx::Slice {
+                    start: 0,
+                    end: vertices.len() as gfx::VertexCount,
+                    base_vertex: 0,
+                    instances: None,
+                    buffer: gfx::IndexBuffer::Auto,
+                };
+                encoder.draw(&slice, &layer.pso, &layer.data);
+            }
         }
     }
 }
 
-// the pre-draw system updates the Drawables with the fresh info
+/// Longer moves of the scroll anchor are jumps, not scrolling.
+const MAX_SCROLL_STEP: f32 = 1.0;
+
+// the pre-draw system updates the Drawables with the fresh info,
+// and tells the painter where the camera is
 pub struct System {
-    extents: [f32; 2],
+    output: mpsc::Sender<View>,
+    debug: Option<mpsc::Sender<Vec<debug::Shape>>>,
+    wrap: bool,
+    glow: config::Glow,
+    scroll: [f32; 2],
+    /// The last position of what the stars scroll after.
+    anchor: Option<[f32; 2]>,
 }
 
 impl System {
-    pub fn new(extents: [f32; 2]) -> System {
+    pub fn new(output: mpsc::Sender<View>, debug: Option<mpsc::Sender<Vec<debug::Shape>>>,
+               wrap: bool, glow: config::Glow) -> System {
         System {
-            extents: extents,
+            output: output,
+            debug: debug,
+            wrap: wrap,
+            glow: glow,
+            scroll: [0.0, 0.0],
+            anchor: None,
+        }
+    }
+
+    fn update_scroll(&mut self, anchor: Option<[f32; 2]>, level: [f32; 2]) {
+        if let (Some(last), Some(pos)) = (self.anchor, anchor) {
+            let mut step = [pos[0] - last[0], pos[1] - last[1]];
+            if self.wrap {
+                step = [wrap_coord(step[0], level[0]), wrap_coord(step[1], level[1])];
+            }
+            if step[0] * step[0] + step[1] * step[1] < MAX_SCROLL_STEP * MAX_SCROLL_STEP {
+                self.scroll = [self.scroll[0] + step[0], self.scroll[1] + step[1]];
+            }
         }
+        self.anchor = anchor;
     }
 }
 
 impl specs::System<pegasus::Delta> for System {
     fn run(&mut self, arg: specs::RunArg, _: pegasus::Delta) {
         use specs::Join;
-        let (mut draw, space) = arg.fetch(|w| {
-            (w.write::<Drawable>(), w.read::<world::Spatial>())
+        let (mut draw, space, control, camera, screen, effects, shapes) = arg.fetch(|w| {
+            // the shapes pushed since the last frame
+            let shapes = mem::replace(&mut w.write_resource::<world::DebugShapes>().0, Vec::new());
+            (w.write::<Drawable>(), w.read::<world::Spatial>(), w.read::<world::Control>(),
+             w.read::<world::Camera>(), *w.read_resource::<world::Screen>(),
+             *w.read_resource::<world::Effects>(), shapes)
         });
-        let scale = [1.0 / self.extents[0], 1.0 / self.extents[1], 0.0, 0.0];
         for (d, s) in (&mut draw, &space).iter() {
-            d.1 = ShaderParam {
-                transform: [s.pos.x, s.pos.y, s.orient.s, s.scale],
-                screen_scale: scale,
-            };
+            d.1.transform = [s.pos.x, s.pos.y, s.orient.s, s.scale];
+        }
+        let mut view = View {
+            pos: [0.0, 0.0],
+            rotation: 0.0,
+            zoom: 1.0,
+            level: screen.level,
+            wrap: self.wrap,
+            minimap: screen.is_level_bigger(),
+            glow: if effects.glow { Some(self.glow) } else { None },
+            scroll: [0.0, 0.0],
+        };
+        let mut following = false;
+        if let Some(cam) = (&camera).iter().next() {
+            view.pos = [cam.pos.x, cam.pos.y];
+            view.rotation = cam.rotation.s;
+            view.zoom = cam.zoom;
+            following = cam.target.is_some();
+        }
+        // the stars scroll after the camera, or after the first ship if the camera stays
+        let anchor = if following {
+            Some(view.pos)
+        } else {
+            (&control, &space).iter()
+                .find(|&(c, _)| c.player == 0)
+                .map(|(_, s)| [s.pos.x, s.pos.y])
+        };
+        self.update_scroll(anchor, view.level);
+        view.scroll = self.scroll;
+        // the painter is gone when the window is closed
+        let _ = self.output.send(view);
+        if let Some(ref debug) = self.debug {
+            let _ = debug.send(shapes);
         }
     }
 }
@@ -0,0 +1,88 @@
+/// Size of a glyph bitmap, in pixels.
+pub const GLYPH_SIZE: [usize; 2] = [5, 7];
+/// Size of a glyph cell in the atlas, including the spacing.
+pub const CELL_SIZE: [usize; 2] = [6, 8];
+const COLUMNS: usize = 16;
+
+const CHARS: &'static str = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:!-.";
+
+// one byte per row, top to bottom, using the lowest 5 bits with the leftmost pixel first
+const GLYPHS: [[u8; 7]; 41] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+];
+
+/// Find the glyph of a character, ignoring the case.
+pub fn glyph_index(c: char) -> Option<usize> {
+    CHARS.find(c.to_ascii_uppercase())
+}
+
+/// Size of the atlas texture, in pixels.
+pub fn atlas_size() -> [usize; 2] {
+    let rows = (GLYPHS.len() + COLUMNS - 1) / COLUMNS;
+    [COLUMNS * CELL_SIZE[0], rows * CELL_SIZE[1]]
+}
+
+/// Render all the glyphs into an RGBA texture, white on transparent.
+pub fn make_atlas() -> Vec<[u8; 4]> {
+    let size = atlas_size();
+    let mut texels = vec![[0u8; 4]; size[0] * size[1]];
+    for (i, glyph) in GLYPHS.iter().enumerate() {
+        let (x0, y0) = ((i % COLUMNS) * CELL_SIZE[0], (i / COLUMNS) * CELL_SIZE[1]);
+        for (y, &row) in glyph.iter().enumerate() {
+            for x in 0 .. GLYPH_SIZE[0] {
+                if row & (1 << (GLYPH_SIZE[0] - 1 - x)) != 0 {
+                    texels[(y0 + y) * size[0] + x0 + x] = [0xFF; 4];
+                }
+            }
+        }
+    }
+    texels
+}
+
+/// Texture coordinates of a glyph cell, as the top-left and bottom-right corners.
+pub fn glyph_rect(index: usize) -> [f32; 4] {
+    let size = atlas_size();
+    let (x0, y0) = ((index % COLUMNS) * CELL_SIZE[0], (index / COLUMNS) * CELL_SIZE[1]);
+    [x0 as f32 / size[0] as f32, y0 as f32 / size[1] as f32,
+     (x0 + CELL_SIZE[0]) as f32 / size[0] as f32, (y0 + CELL_SIZE[1]) as f32 / size[1] as f32]
+}
@@ -0,0 +1,79 @@
+use std::sync::mpsc;
+use specs;
+use world as w;
+use super::draw::{Align, Text};
+
+
+const TEXT_SIZE: f32 = 0.05;
+const MESSAGE_SIZE: f32 = 0.15;
+const TEXT_COLOR: u32 = 0xFFFFFF00;
+const MESSAGE_COLOR: u32 = 0xFF404000;
+/// Distance of the text from the screen edges.
+const MARGIN: f32 = 0.05;
+
+// the HUD system describes the game status with text,
+// sending it to the painter every frame
+pub struct System {
+    output: mpsc::Sender<Vec<Text>>,
+    pause: super::PauseFlag,
+}
+
+impl System {
+    pub fn new(output: mpsc::Sender<Vec<Text>>, pause: super::PauseFlag) -> System {
+        System {
+            output: output,
+            pause: pause,
+        }
+    }
+}
+
+fn line(x: f32, y: f32, size: f32, align: Align, color: u32, text: String) -> Text {
+    Text {
+        pos: [x, y],
+        size: size,
+        align: align,
+        color: color,
+        text: text,
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, _: super::Delta) {
+        let status = arg.fetch(|w| w.read_resource::<w::Status>().clone());
+        let top = 1.0 - MARGIN;
+        let mut texts = vec![
+            line(0.0, top, TEXT_SIZE, Align::Center, TEXT_COLOR, format!("WAVE {}", status.wave)),
+        ];
+        // players go to the left and right corners in turn
+        for (i, ps) in status.players.iter().enumerate() {
+            let (x, align) = if i % 2 == 0 {
+                (MARGIN - 1.0, Align::Left)
+            } else {
+                (1.0 - MARGIN, Align::Right)
+            };
+            let y = top - (i / 2) as f32 * 3.0 * TEXT_SIZE;
+            let name = if status.players.len() > 1 {
+                format!("P{} ", i + 1)
+            } else {
+                String::new()
+            };
+            texts.push(line(x, y, TEXT_SIZE, align, TEXT_COLOR,
+                format!("{}SCORE {}", name, ps.score)));
+            texts.push(line(x, y - 1.5 * TEXT_SIZE, TEXT_SIZE, align, TEXT_COLOR,
+                format!("LIVES {}", ps.lives)));
+        }
+        let message = if status.is_game_over() {
+            Some("GAME OVER")
+        } else if super::is_paused(&self.pause) {
+            Some("PAUSED")
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            texts.push(line(0.0, 0.5 * MESSAGE_SIZE, MESSAGE_SIZE, Align::Center,
+                MESSAGE_COLOR, message.to_string()));
+        }
+        // the painter is gone when the window is closed
+        let _ = self.output.send(texts);
+    }
+}
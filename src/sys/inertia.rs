@@ -1,17 +1,42 @@
 use specs;
 use world as w;
 
-pub struct System;
+pub struct System {
+	wrap: Option<[f32; 2]>,
+}
+
+impl System {
+	/// Create the system, optionally wrapping the positions
+	/// around the given extents, making the space toroidal.
+	pub fn new(wrap: Option<[f32; 2]>) -> System {
+		System {
+			wrap: wrap,
+		}
+	}
+}
+
+/// Bring the coordinate back into the [-extent, extent) range.
+pub fn wrap_coord(x: f32, extent: f32) -> f32 {
+	x - 2.0 * extent * ((x + extent) / (2.0 * extent)).floor()
+}
 
 impl specs::System<super::Delta> for System {
 	fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
 		use specs::Join;
-		let (mut space, inertia) = arg.fetch(|w|
+		let (mut space, inertia) = arg.fetch(|w| {
+			// the wrapped space follows the level
+			if let Some(ref mut ext) = self.wrap {
+				*ext = w.read_resource::<w::Screen>().level;
+			}
 			(w.write::<w::Spatial>(), w.read::<w::Inertial>())
-		);
+		});
 		for (s, i) in (&mut space, &inertia).iter() {
 			s.pos = s.pos + i.velocity * time;
             s.orient = s.orient + i.angular_velocity * time;
+			if let Some(ext) = self.wrap {
+				s.pos.x = wrap_coord(s.pos.x, ext[0]);
+				s.pos.y = wrap_coord(s.pos.y, ext[1]);
+			}
 		}
 	}
 }
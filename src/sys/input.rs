@@ -0,0 +1,81 @@
+use std::sync::atomic::Ordering;
+use specs;
+use event;
+use world as w;
+
+/// Where the input events come from.
+pub enum Source {
+    Live(event::ReceiverHub),
+    Replay(event::Player),
+}
+
+// the input system runs before everything else, collecting the events
+// for the current frame, recording them, and forwarding to other systems
+pub struct System {
+    source: Source,
+    output: event::SenderHub,
+    recorder: Option<event::Recorder>,
+    frame: event::Frame,
+    pause: super::PauseFlag,
+    /// Can the debug shapes be switched on. They pile up unless
+    /// there is someone to draw them.
+    debug: bool,
+}
+
+impl System {
+    pub fn new(source: Source, output: event::SenderHub, recorder: Option<event::Recorder>,
+               pause: super::PauseFlag, debug: bool) -> System
+    {
+        System {
+            source: source,
+            output: output,
+            recorder: recorder,
+            frame: 0,
+            pause: pause,
+            debug: debug,
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        // done before the other systems start, so that they see the events
+        arg.fetch(|w| {
+            let inputs = match self.source {
+                Source::Live(ref hub) => hub.drain(),
+                Source::Replay(ref mut player) => player.fetch(self.frame),
+            };
+            if let Some(ref mut rec) = self.recorder {
+                rec.record_frame(self.frame, time, &inputs).unwrap();
+            }
+            for input in inputs {
+                match input {
+                    event::Input::Pause => {
+                        let paused = super::is_paused(&self.pause);
+                        self.pause.store(!paused, Ordering::Relaxed);
+                    },
+                    event::Input::Glow => {
+                        let mut effects = w.write_resource::<w::Effects>();
+                        effects.glow = !effects.glow;
+                    },
+                    event::Input::Debug if self.debug => {
+                        let mut effects = w.write_resource::<w::Effects>();
+                        effects.debug = !effects.debug;
+                    },
+                    event::Input::Debug => (),
+                    event::Input::Resize(width, height) => {
+                        let mut screen = w.write_resource::<w::Screen>();
+                        // the level takes the shape of the first window
+                        *screen = if self.frame == 0 {
+                            screen.fit_level([width, height])
+                        } else {
+                            screen.fit([width, height])
+                        };
+                    },
+                    _ => self.output.send(input),
+                }
+            }
+            self.frame += 1;
+        });
+    }
+}
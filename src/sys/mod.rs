@@ -1,8 +1,57 @@
+pub mod anim;
 pub mod aster;
 pub mod bullet;
+pub mod camera;
 pub mod control;
+pub mod debug;
 pub mod draw;
+pub mod font;
+pub mod hud;
 pub mod inertia;
+pub mod input;
+pub mod particle;
 pub mod physics;
+pub mod raster;
+pub mod score;
+pub mod ship;
+pub mod stars;
+pub mod tint;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use specs;
 
 pub type Delta = f32;
+
+/// Shared switch stopping the simulation while set.
+pub type PauseFlag = Arc<AtomicBool>;
+
+pub fn is_paused(flag: &PauseFlag) -> bool {
+    flag.load(Ordering::Relaxed)
+}
+
+/// Wraps a system, skipping it while the game is paused.
+pub struct Pausable<S> {
+    flag: PauseFlag,
+    system: S,
+}
+
+impl<S> Pausable<S> {
+    pub fn new(flag: PauseFlag, system: S) -> Pausable<S> {
+        Pausable {
+            flag: flag,
+            system: system,
+        }
+    }
+}
+
+impl<S: specs::System<Delta>> specs::System<Delta> for Pausable<S> {
+    fn run(&mut self, arg: specs::RunArg, time: Delta) {
+        if is_paused(&self.flag) {
+            // the planner expects every system to fetch
+            arg.fetch(|_| ());
+        } else {
+            self.system.run(arg, time);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use rand::{Rng, SeedableRng, StdRng};
+use cgmath::{Rad, Point2, Vector2, Basis2, Rotation, Rotation2};
+use specs;
+use config;
+use world as w;
+
+
+const DEBRIS_COLOR: [f32; 4] = [1.0, 0.9, 0.6, 1.0];
+/// Maximum deviation of the exhaust from the backward direction.
+const EXHAUST_SPREAD: f32 = 0.3;
+
+// the particle system spawns debris out of the destroyed objects
+// and exhaust out of the active emitters, fading the particles out
+pub struct System {
+    drawable: w::Drawable,
+    rng: StdRng,
+    config: config::Particle,
+}
+
+impl System {
+    pub fn new(drawable: w::Drawable, seed: usize, config: config::Particle) -> System {
+        System {
+            drawable: drawable,
+            // keep the sequence apart from the ones of the other systems
+            rng: StdRng::from_seed(&[seed, 2]),
+            config: config,
+        }
+    }
+
+    fn create(&self, w: &specs::World, pos: Point2<f32>, velocity: Vector2<f32>,
+              color: [f32; 4]) -> specs::Entity {
+        w.create_later_build()
+            .with(self.drawable.clone())
+            .with(w::Spatial {
+                pos: pos,
+                orient: Rad{ s: 0.0 },
+                scale: 0.05,
+            })
+            .with(w::Inertial {
+                velocity: velocity,
+                angular_velocity: Rad{ s: 0.0 },
+            })
+            .with(w::Particle {
+                life_time: self.config.life_time,
+                time_left: self.config.life_time,
+                color: color,
+            })
+            .build()
+    }
+
+    fn spawn_debris(&mut self, w: &specs::World) {
+        let origins: Vec<_> = {
+            let (contacts, bullet, inertia) = (w.read_resource::<w::Contacts>(),
+                w.read::<w::Bullet>(), w.read::<w::Inertial>());
+            let mut origins = Vec::new();
+            for contact in contacts.0.iter() {
+                for (&e, &destroyed) in contact.entities.iter().zip(contact.destroyed.iter()) {
+                    // bullets are too small to leave anything behind
+                    if !destroyed || bullet.get(e).is_some() ||
+                        origins.iter().any(|&(e2, _, _)| e2 == e) {
+                        continue
+                    }
+                    // the debris bursts out where the hit was
+                    if let Some(i) = inertia.get(e) {
+                        origins.push((e, contact.point, i.velocity));
+                    }
+                }
+            }
+            origins
+        };
+        for (_, pos, velocity) in origins {
+            for _ in 0 .. self.config.debris_count {
+                let angle = self.rng.gen_range(0.0, 2.0 * ::std::f32::consts::PI);
+                let speed = self.config.debris_speed * self.rng.gen_range(0.2, 1.0);
+                let dir = Vector2::new(angle.cos(), angle.sin());
+                self.create(w, pos, velocity + dir * speed, DEBRIS_COLOR);
+            }
+        }
+    }
+
+    fn spawn_exhaust(&mut self, w: &specs::World, time: super::Delta) {
+        use specs::Join;
+        let mut sources = Vec::new();
+        {
+            let (mut emitter, space, inertia) = (w.write::<w::Emitter>(),
+                w.read::<w::Spatial>(), w.read::<w::Inertial>());
+            for (em, s, i) in (&mut emitter, &space, &inertia).iter() {
+                if !em.active {
+                    em.pending = 0.0;
+                    continue
+                }
+                em.pending += em.rate * time;
+                while em.pending >= 1.0 {
+                    em.pending -= 1.0;
+                    sources.push((s.clone(), i.velocity, em.speed, em.color));
+                }
+            }
+        }
+        for (s, velocity, speed, color) in sources {
+            let spread = self.rng.gen_range(-EXHAUST_SPREAD, EXHAUST_SPREAD);
+            let rot: Basis2<f32> = Rotation2::from_angle(Rad{ s: spread });
+            let dir = -rot.rotate_vector(s.get_direction());
+            let speed = speed * self.rng.gen_range(0.5, 1.0);
+            self.create(w, s.pos + dir * (0.5 * s.scale), velocity + dir * speed, color);
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        use specs::Join;
+        let (mut particle, mut draw, entities) = arg.fetch(|w| {
+            self.spawn_debris(w);
+            self.spawn_exhaust(w, time);
+            (w.write::<w::Particle>(), w.write::<w::Drawable>(), w.entities())
+        });
+        for (p, d, e) in (&mut particle, &mut draw, &entities).iter() {
+            p.time_left -= time;
+            if p.time_left <= 0.0 {
+                arg.delete(e);
+                continue
+            }
+            let fade = p.time_left / p.life_time;
+            d.set_tint([p.color[0], p.color[1], p.color[2], p.color[3] * fade]);
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use std::collections::HashMap;
-use cgmath::{EuclideanVector};
+use std::collections::{HashMap, HashSet};
+use cgmath::{EuclideanVector, Point2, Vector2};
 use specs;
 use world as w;
 
 
-const CELL_SIZE: f32 = 1.0;
+pub const CELL_SIZE: f32 = 1.0;
+const DEBUG_CELL_COLOR: u32 = 0x40404000;
+const DEBUG_RADIUS_COLOR: u32 = 0x00FF0000;
+const DEBUG_VELOCITY_COLOR: u32 = 0xFFFF0000;
 const OFFSETS: [(i32, i32); 9] = [(0, 0),
     (1, 0), (0, 1), (-1, 0), (0, -1),
     (1, 1), (1, -1), (-1, 1), (-1, -1)];
@@ -14,44 +17,165 @@ pub struct Cell(i32, i32);
 
 pub struct System {
     grid: HashMap<Cell, Vec<(specs::Entity, u16)>>,
+    destroyed: HashSet<specs::Entity>,
+    wrap: Option<[f32; 2]>,
+    friendly_fire: bool,
 }
 
 impl System {
-    pub fn new() -> System {
+    /// Create the system, optionally looking for collisions
+    /// across the edges of the wrapped space. Without the friendly fire,
+    /// bullets pass through the ships.
+    pub fn new(wrap: Option<[f32; 2]>, friendly_fire: bool) -> System {
         System {
             grid: HashMap::new(),
+            destroyed: HashSet::new(),
+            wrap: wrap,
+            friendly_fire: friendly_fire,
         }
     }
+
+    fn get_cell(&self, pos: Point2<f32>) -> Cell {
+        match self.wrap {
+            Some(ext) => {
+                let (_, size) = get_wrapped_grid(ext);
+                self.wrap_cell(
+                    ((pos.x + ext[0]) / size[0]).floor() as i32,
+                    ((pos.y + ext[1]) / size[1]).floor() as i32)
+            },
+            None => Cell((pos.x / CELL_SIZE) as i32, (pos.y / CELL_SIZE) as i32),
+        }
+    }
+
+    // the area of a cell, as the lower left and the upper right corners
+    fn get_cell_rect(&self, cell: &Cell) -> [f32; 4] {
+        let (x, y) = match self.wrap {
+            Some(ext) => {
+                let (_, size) = get_wrapped_grid(ext);
+                let x = cell.0 as f32 * size[0] - ext[0];
+                let y = cell.1 as f32 * size[1] - ext[1];
+                ((x, x + size[0]), (y, y + size[1]))
+            },
+            None => {
+                // the coordinates are truncated towards zero,
+                // so the cells next to the axes are twice as big
+                let range = |i: i32| match i {
+                    0 => (-CELL_SIZE, CELL_SIZE),
+                    i if i > 0 => (i as f32 * CELL_SIZE, (i + 1) as f32 * CELL_SIZE),
+                    i => ((i - 1) as f32 * CELL_SIZE, i as f32 * CELL_SIZE),
+                };
+                (range(cell.0), range(cell.1))
+            },
+        };
+        [x.0, y.0, x.1, y.1]
+    }
+
+    fn wrap_cell(&self, x: i32, y: i32) -> Cell {
+        match self.wrap {
+            Some(ext) => {
+                let ([nx, ny], _) = get_wrapped_grid(ext);
+                Cell(((x % nx) + nx) % nx, ((y % ny) + ny) % ny)
+            },
+            None => Cell(x, y),
+        }
+    }
+}
+
+// the wrapped level is covered by a whole number of cells in each direction,
+// stretched to be no smaller than `CELL_SIZE`, so that the neighbors
+// across the edges are as close as the others
+fn get_wrapped_grid(ext: [f32; 2]) -> ([i32; 2], [f32; 2]) {
+    let nx = ((2.0 * ext[0] / CELL_SIZE).floor() as i32).max(1);
+    let ny = ((2.0 * ext[1] / CELL_SIZE).floor() as i32).max(1);
+    ([nx, ny], [2.0 * ext[0] / nx as f32, 2.0 * ext[1] / ny as f32])
+}
+
+// shortest vector from one point to another
+fn get_offset(wrap: Option<[f32; 2]>, from: Point2<f32>, to: Point2<f32>) -> Vector2<f32> {
+    let mut d = to - from;
+    if let Some(ext) = wrap {
+        d.x -= 2.0 * ext[0] * (d.x / (2.0 * ext[0])).round();
+        d.y -= 2.0 * ext[1] * (d.y / (2.0 * ext[1])).round();
+    }
+    d
 }
 
 impl specs::System<super::Delta> for System {
     fn run(&mut self, arg: specs::RunArg, _: super::Delta) {
         use specs::Join;
         let mut empty = Vec::new();
-        let (space, mut collision, entities) = arg.fetch(|w|
-            (w.read::<w::Spatial>(), w.write::<w::Collision>(), w.entities())
-        );
+        let (space, mut collision, invulnerable, bullet, control, aster, inertia,
+             mut contacts, mut debug, entities) = arg.fetch(|w| {
+            // the wrapped space follows the level
+            if let Some(ref mut ext) = self.wrap {
+                *ext = w.read_resource::<w::Screen>().level;
+            }
+            let debug = if w.read_resource::<w::Effects>().debug {
+                Some(w.write_resource::<w::DebugShapes>())
+            } else {
+                None
+            };
+            (w.read::<w::Spatial>(), w.write::<w::Collision>(), w.read::<w::Invulnerable>(),
+             w.read::<w::Bullet>(), w.read::<w::Control>(), w.read::<w::Asteroid>(),
+             w.read::<w::Inertial>(),
+             w.write_resource::<w::Contacts>(), debug, w.entities())
+        });
+        // bullets never hit their own ship, and asteroids fly through each other
+        let friendly_fire = self.friendly_fire;
+        let passes = |e1: specs::Entity, e2: specs::Entity| {
+            match (bullet.get(e1), control.get(e2)) {
+                (Some(b), Some(c)) => !friendly_fire || b.owner == c.player,
+                _ => aster.get(e1).is_some() && aster.get(e2).is_some(),
+            }
+        };
+        contacts.0.clear();
         for (sp, col, ent) in (&space, &collision, &entities).iter() {
-            let cell = Cell((sp.pos.x / CELL_SIZE) as i32, (sp.pos.y / CELL_SIZE) as i32);
+            if invulnerable.get(ent).is_some() {
+                continue
+            }
+            let cell = self.get_cell(sp.pos);
             let mut damage = 0;
             for &(ofx, ofy) in OFFSETS.iter() {
-                let cell2 = Cell(cell.0 + ofx, cell.1 + ofy);
+                let cell2 = self.wrap_cell(cell.0 + ofx, cell.1 + ofy);
                 for &mut (e2, ref mut dam2) in self.grid.get_mut(&cell2).unwrap_or(&mut empty).iter_mut() {
                     let s2 = space.get(e2).unwrap();
                     let c2 = collision.get(e2).unwrap();
-                    let dist_sq = (sp.pos - s2.pos).magnitude2();
+                    let offset = get_offset(self.wrap, sp.pos, s2.pos);
+                    let dist_sq = offset.magnitude2();
                     let diam = col.radius + c2.radius;
                     assert!(diam <= CELL_SIZE);
+                    if passes(ent, e2) || passes(e2, ent) {
+                        continue
+                    }
                     if c2.health > *dam2 && dist_sq < diam*diam {
                         *dam2 += col.damage;
                         damage += c2.damage;
+                        contacts.0.push(w::Contact {
+                            entities: [ent, e2],
+                            damage: [c2.damage, col.damage],
+                            point: sp.pos + offset * (col.radius / diam),
+                            destroyed: [false, false],
+                        });
                     }
                 }
             }
-            if col.health > damage {
-                self.grid.entry(cell).or_insert(Vec::new()).push((ent, damage));
-            }else {
-                arg.delete(ent);
+            // keep it in the grid even if destroyed, so that it gets
+            // cleaned up together with the others
+            self.grid.entry(cell).or_insert(Vec::new()).push((ent, damage));
+        }
+        if let Some(ref mut debug) = debug {
+            for (cell, list) in self.grid.iter() {
+                if !list.is_empty() {
+                    debug.0.push(w::DebugShape::Rect(self.get_cell_rect(cell), DEBUG_CELL_COLOR));
+                }
+            }
+            for (s, c) in (&space, &collision).iter() {
+                debug.0.push(w::DebugShape::Circle([s.pos.x, s.pos.y], c.radius, DEBUG_RADIUS_COLOR));
+            }
+            for (s, _, i) in (&space, &collision, &inertia).iter() {
+                let end = s.pos + i.velocity;
+                debug.0.push(w::DebugShape::Line([s.pos.x, s.pos.y], [end.x, end.y],
+                    DEBUG_VELOCITY_COLOR));
             }
         }
         // clean up and delete more stuff
@@ -61,9 +185,17 @@ impl specs::System<super::Delta> for System {
                 if c.health > damage {
                     c.health -= damage;
                 }else {
+                    self.destroyed.insert(e);
                     arg.delete(e)
                 }
             }
         }
+        for contact in contacts.0.iter_mut() {
+            contact.destroyed = [
+                self.destroyed.contains(&contact.entities[0]),
+                self.destroyed.contains(&contact.entities[1]),
+            ];
+        }
+        self.destroyed.clear();
     }
 }
@@ -0,0 +1,450 @@
+use std::cmp::Ordering;
+use std::sync::mpsc;
+
+use gfx;
+use pegasus;
+
+use image::Image;
+use world;
+use super::draw::{self, Drawable, Instance, ShaderParam, Sprite, Text, Vertex, View};
+use super::{font, stars};
+
+
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+struct Visual {
+    primitive: gfx::Primitive,
+    vertices: Vec<Vertex>,
+    texture: Option<Texture>,
+}
+
+impl Visual {
+    // the texture color at the barycentric weights of a triangle,
+    // or nothing where it's fully transparent, like the sprite shaders
+    fn get_texel(&self, tri: [usize; 3], w: [f32; 3], frame: f32) -> Option<[f32; 4]> {
+        let tex = match self.texture {
+            Some(ref tex) => tex,
+            None => return Some([1.0; 4]),
+        };
+        let (a, b, c) = (tex.coords[tri[0]], tex.coords[tri[1]], tex.coords[tri[2]]);
+        let uv = [w[0] * a[0] + w[1] * b[0] + w[2] * c[0],
+                  w[0] * a[1] + w[1] * b[1] + w[2] * c[1]];
+        let texel = tex.sample(uv, frame);
+        if texel[3] == 0.0 { None } else { Some(texel) }
+    }
+}
+
+struct Texture {
+    image: Image,
+    /// Coordinates of each vertex, within a single frame.
+    coords: Vec<[f32; 2]>,
+    grid: [f32; 2],
+    sampler: gfx::texture::SamplerInfo,
+}
+
+fn wrap_texel(i: i32, size: u32, mode: gfx::texture::WrapMode) -> u32 {
+    use gfx::texture::WrapMode;
+    let n = size as i32;
+    let i = match mode {
+        WrapMode::Tile => ((i % n) + n) % n,
+        WrapMode::Mirror => {
+            let m = ((i % (2 * n)) + 2 * n) % (2 * n);
+            if m < n { m } else { 2 * n - 1 - m }
+        },
+        WrapMode::Clamp | WrapMode::Border => i.max(0).min(n - 1),
+    };
+    i as u32
+}
+
+impl Texture {
+    fn fetch(&self, x: i32, y: i32) -> [f32; 4] {
+        let (wrap_u, wrap_v, _) = self.sampler.wrap_mode;
+        let size = self.image.size;
+        let (x, y) = (wrap_texel(x, size[0], wrap_u), wrap_texel(y, size[1], wrap_v));
+        let p = self.image.pixels[(y * size[0] + x) as usize];
+        [from_srgb(p[0]), from_srgb(p[1]), from_srgb(p[2]), p[3] as f32 / 255.0]
+    }
+
+    fn sample(&self, uv: [f32; 2], frame: f32) -> [f32; 4] {
+        use gfx::texture::FilterMethod;
+        let column = frame % self.grid[0];
+        let row = (frame / self.grid[0]).floor() % self.grid[1];
+        let x = (uv[0] + column) / self.grid[0] * self.image.size[0] as f32 - 0.5;
+        let y = (uv[1] + row) / self.grid[1] * self.image.size[1] as f32 - 0.5;
+        match self.sampler.filter {
+            FilterMethod::Scale | FilterMethod::Mipmap =>
+                self.fetch((x + 0.5).floor() as i32, (y + 0.5).floor() as i32),
+            _ => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let (t00, t10) = (self.fetch(x0, y0), self.fetch(x0 + 1, y0));
+                let (t01, t11) = (self.fetch(x0, y0 + 1), self.fetch(x0 + 1, y0 + 1));
+                let mut out = [0.0; 4];
+                for i in 0 .. 4 {
+                    let top = t00[i] + (t10[i] - t00[i]) * fx;
+                    let bottom = t01[i] + (t11[i] - t01[i]) * fx;
+                    out[i] = top + (bottom - top) * fy;
+                }
+                out
+            },
+        }
+    }
+}
+
+struct TextLayer {
+    input: mpsc::Receiver<Vec<Text>>,
+    output: mpsc::Sender<Vec<Text>>,
+    texts: Vec<Text>,
+    atlas: Vec<[u8; 4]>,
+}
+
+/// Pixel rectangle to draw into, as the first column and row,
+/// followed by the ones past the last.
+type Clip = [i32; 4];
+
+// same math as the vertex shader of the painter
+fn rotate(v: [f32; 2], angle: f32) -> [f32; 2] {
+    let (s, c) = angle.sin_cos();
+    [v[0] * c - v[1] * s, v[0] * s + v[1] * c]
+}
+
+fn project(pos: [f32; 2], inst: &Instance, param: &ShaderParam) -> [f32; 2] {
+    let t = inst.transform;
+    let r = rotate(pos, t[2]);
+    let v = param.view;
+    let p = rotate([r[0] * t[3] + t[0] - v[0], r[1] * t[3] + t[1] - v[1]], -v[2]);
+    [p[0] * v[3] * param.screen[0] + param.screen[2],
+     p[1] * v[3] * param.screen[1] + param.screen[3]]
+}
+
+fn to_linear(c: [gfx::format::U8Norm; 4]) -> [f32; 4] {
+    [c[0].0 as f32 / 255.0, c[1].0 as f32 / 255.0, c[2].0 as f32 / 255.0, c[3].0 as f32 / 255.0]
+}
+
+fn unpack(col: u32) -> [f32; 4] {
+    [(col >> 24) as u8 as f32 / 255.0, (col >> 16) as u8 as f32 / 255.0,
+     (col >> 8) as u8 as f32 / 255.0, col as u8 as f32 / 255.0]
+}
+
+// the target is sRGB, so the colors are encoded when written, like the GPU does
+fn to_srgb(c: f32) -> u8 {
+    let c = c.max(0.0).min(1.0);
+    let s = if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0 + 0.5) as u8
+}
+
+fn from_srgb(s: u8) -> f32 {
+    let s = s as f32 / 255.0;
+    if s <= 0.04045 {
+        s / 12.92
+    } else {
+        ((s + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode(c: [f32; 4]) -> [u8; 4] {
+    // the window is opaque, so the alpha is not kept
+    [to_srgb(c[0]), to_srgb(c[1]), to_srgb(c[2]), 0xFF]
+}
+
+// same as the alpha blending of the painter, which happens in the linear space
+fn blend(dst: [u8; 4], c: [f32; 4]) -> [u8; 4] {
+    let a = c[3].max(0.0).min(1.0);
+    let over = |i: usize| c[i].min(1.0) * a + from_srgb(dst[i]) * (1.0 - a);
+    encode([over(0), over(1), over(2), 1.0])
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// covers the pixels with their centers inside the triangle, in any winding,
+// asking the shader for the color at the barycentric weights of each one
+fn fill_triangle<F>(image: &mut Image, clip: Clip, p: [[f32; 2]; 3], mut shade: F) where
+    F: FnMut([f32; 3]) -> Option<[f32; 4]>,
+{
+    let area = edge(p[0], p[1], p[2]);
+    if area == 0.0 {
+        return
+    }
+    let x0 = (p[0][0].min(p[1][0]).min(p[2][0]).floor() as i32).max(clip[0]);
+    let y0 = (p[0][1].min(p[1][1]).min(p[2][1]).floor() as i32).max(clip[1]);
+    let x1 = (p[0][0].max(p[1][0]).max(p[2][0]).ceil() as i32).min(clip[2]);
+    let y1 = (p[0][1].max(p[1][1]).max(p[2][1]).ceil() as i32).min(clip[3]);
+    for y in y0 .. y1 {
+        for x in x0 .. x1 {
+            let c = [x as f32 + 0.5, y as f32 + 0.5];
+            let w = [edge(p[1], p[2], c) / area, edge(p[2], p[0], c) / area,
+                     edge(p[0], p[1], c) / area];
+            if w[0] < 0.0 || w[1] < 0.0 || w[2] < 0.0 {
+                continue
+            }
+            if let Some(color) = shade(w) {
+                let pixel = &mut image.pixels[(y as u32 * image.size[0] + x as u32) as usize];
+                *pixel = blend(*pixel, color);
+            }
+        }
+    }
+}
+
+fn modulate(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+}
+
+fn mix(w: [f32; 3], v: [[f32; 4]; 3]) -> [f32; 4] {
+    let mut out = [0.0; 4];
+    for i in 0 .. 4 {
+        out[i] = w[0] * v[0][i] + w[1] * v[1][i] + w[2] * v[2][i];
+    }
+    out
+}
+
+/// Draws the same visuals as the `Painter`, but on the CPU into an image,
+/// for the machines without a GPU and for comparing frames with the stored ones.
+/// The glow is left out, it's only a look.
+pub struct Rasterizer {
+    visuals: Vec<Visual>,
+    /// Instances collected for each visual during a frame.
+    batches: Vec<Vec<Instance>>,
+    minimap: Vec<Vec<Instance>>,
+    text: Option<TextLayer>,
+    stars: Vec<stars::Layer>,
+    view_input: mpsc::Receiver<View>,
+    view_output: mpsc::Sender<View>,
+    view: View,
+    extents: [f32; 2],
+    image: Image,
+}
+
+impl Rasterizer {
+    pub fn new(size: [u32; 2]) -> Rasterizer {
+        let (view_output, view_input) = mpsc::channel();
+        Rasterizer {
+            visuals: Vec::new(),
+            batches: Vec::new(),
+            minimap: Vec::new(),
+            text: None,
+            stars: Vec::new(),
+            view_input: view_input,
+            view_output: view_output,
+            view: View::default(),
+            extents: [1.0, 1.0],
+            image: Image::new(size, encode(CLEAR_COLOR)),
+        }
+    }
+
+    /// Set the half-size of the visible area, in world units.
+    /// Its width is adjusted to the aspect ratio of the image.
+    pub fn set_extents(&mut self, extents: [f32; 2]) {
+        self.extents = extents;
+    }
+
+    /// Get the channel to send the camera views to.
+    pub fn get_view_sender(&self) -> mpsc::Sender<View> {
+        self.view_output.clone()
+    }
+
+    /// The picture of the last frame drawn.
+    pub fn get_image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn add_visual(&mut self, primitive: gfx::Primitive, vertices: &[Vertex]) -> Drawable {
+        match primitive {
+            gfx::Primitive::PointList |
+            gfx::Primitive::TriangleList |
+            gfx::Primitive::TriangleStrip => (),
+            other => panic!("Primitive {:?} is not supported by the rasterizer", other),
+        }
+        let id = self.visuals.len();
+        self.visuals.push(Visual {
+            primitive: primitive,
+            vertices: vertices.to_vec(),
+            texture: None,
+        });
+        self.batches.push(Vec::new());
+        self.minimap.push(Vec::new());
+        Drawable::new(id)
+    }
+
+    pub fn add_sprite(&mut self, image: &Image, sprite: &Sprite) -> Drawable {
+        let corners = sprite.get_vertices();
+        let id = self.visuals.len();
+        self.visuals.push(Visual {
+            primitive: gfx::Primitive::TriangleStrip,
+            vertices: corners.iter().map(|v| Vertex::new(v.pos[0], v.pos[1], 0xFFFFFFFF)).collect(),
+            texture: Some(Texture {
+                image: image.clone(),
+                coords: corners.iter().map(|v| v.tex_coord).collect(),
+                grid: [sprite.grid[0] as f32, sprite.grid[1] as f32],
+                sampler: sprite.sampler,
+            }),
+        });
+        self.batches.push(Vec::new());
+        self.minimap.push(Vec::new());
+        Drawable::new(id)
+    }
+
+    /// Prepare the text drawing, returning the channel to send the lines to draw.
+    /// Each set of lines sent replaces the previous one.
+    pub fn add_text(&mut self) -> mpsc::Sender<Vec<Text>> {
+        if let Some(ref layer) = self.text {
+            return layer.output.clone()
+        }
+        let (output, input) = mpsc::channel();
+        self.text = Some(TextLayer {
+            input: input,
+            output: output.clone(),
+            texts: Vec::new(),
+            atlas: font::make_atlas(),
+        });
+        output
+    }
+
+    /// Prepare the starfield in the background, placed after the seed.
+    pub fn add_stars(&mut self, seed: usize) {
+        self.stars = stars::make_layers(seed);
+    }
+
+    fn draw_stars(&mut self, scroll: [f32; 2], extents: [f32; 2]) {
+        let size = self.image.size;
+        for layer in self.stars.iter() {
+            let offset = stars::get_offset(layer.parallax, scroll, extents);
+            let c = unpack(layer.color);
+            for &star in layer.stars.iter() {
+                let p = stars::project(star, offset);
+                let x = (((p[0] + 1.0) * 0.5 * size[0] as f32) as u32).min(size[0] - 1);
+                let y = (((1.0 - p[1]) * 0.5 * size[1] as f32) as u32).min(size[1] - 1);
+                self.image.pixels[(y * size[0] + x) as usize] = encode(c);
+            }
+        }
+    }
+
+    fn to_pixels(&self, p: [f32; 2]) -> [f32; 2] {
+        [(p[0] + 1.0) * 0.5 * self.image.size[0] as f32,
+         (1.0 - p[1]) * 0.5 * self.image.size[1] as f32]
+    }
+
+    fn draw_batches(&mut self, param: &ShaderParam, clip: Clip, minimap: bool) {
+        let batches = if minimap { &self.minimap } else { &self.batches };
+        // there is no depth buffer, so the instances are painted from the back,
+        // keeping the order of the painter within a layer
+        let mut order: Vec<_> = batches.iter().enumerate().flat_map(|(i, batch)|
+            batch.iter().map(move |inst| (i, inst))
+        ).collect();
+        order.sort_by(|a, b| b.1.depth.partial_cmp(&a.1.depth).unwrap_or(Ordering::Equal));
+        let colors: Vec<Vec<_>> = self.visuals.iter().map(|visual|
+            visual.vertices.iter().map(|v| to_linear(v.color)).collect()
+        ).collect();
+        for (id, inst) in order {
+            let visual = &self.visuals[id];
+            let colors = &colors[id];
+            let points: Vec<_> = visual.vertices.iter().map(|v|
+                self.to_pixels(project(v.pos, inst, param))
+            ).collect();
+            let shade = |c: [f32; 4]| modulate(c, inst.tint);
+            match visual.primitive {
+                gfx::Primitive::PointList => for (p, &c) in points.iter().zip(colors.iter()) {
+                    let (x, y) = (p[0].floor() as i32, p[1].floor() as i32);
+                    if x >= clip[0] && x < clip[2] && y >= clip[1] && y < clip[3] {
+                        let index = (y as u32 * self.image.size[0] + x as u32) as usize;
+                        self.image.pixels[index] = blend(self.image.pixels[index], shade(c));
+                    }
+                },
+                gfx::Primitive::TriangleList => for i in 0 .. points.len() / 3 {
+                    let k = 3 * i;
+                    let tri = [colors[k], colors[k + 1], colors[k + 2]];
+                    fill_triangle(&mut self.image, clip, [points[k], points[k + 1], points[k + 2]],
+                        |w| visual.get_texel([k, k + 1, k + 2], w, inst.frame)
+                            .map(|t| shade(modulate(mix(w, tri), t))));
+                },
+                gfx::Primitive::TriangleStrip => for k in 0 .. points.len().saturating_sub(2) {
+                    let tri = [colors[k], colors[k + 1], colors[k + 2]];
+                    fill_triangle(&mut self.image, clip, [points[k], points[k + 1], points[k + 2]],
+                        |w| visual.get_texel([k, k + 1, k + 2], w, inst.frame)
+                            .map(|t| shade(modulate(mix(w, tri), t))));
+                },
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn draw_text(&mut self) {
+        let layer = match self.text {
+            Some(ref mut layer) => layer,
+            None => return,
+        };
+        while let Ok(texts) = layer.input.try_recv() {
+            layer.texts = texts;
+        }
+        let size = self.image.size;
+        let aspect = size[0] as f32 / size[1].max(1) as f32;
+        let atlas_size = font::atlas_size();
+        let clip = [0, 0, size[0] as i32, size[1] as i32];
+        let vertices = draw::layout_text(&layer.texts, aspect);
+        for tri in vertices.chunks(3) {
+            let points = [tri[0].pos, tri[1].pos, tri[2].pos];
+            let points: Vec<_> = points.iter().map(|p|
+                [(p[0] + 1.0) * 0.5 * size[0] as f32, (1.0 - p[1]) * 0.5 * size[1] as f32]
+            ).collect();
+            let colors = [to_linear(tri[0].color), to_linear(tri[1].color), to_linear(tri[2].color)];
+            let atlas = &layer.atlas;
+            fill_triangle(&mut self.image, clip, [points[0], points[1], points[2]], |w| {
+                let u = w[0] * tri[0].tex_coord[0] + w[1] * tri[1].tex_coord[0] + w[2] * tri[2].tex_coord[0];
+                let v = w[0] * tri[0].tex_coord[1] + w[1] * tri[1].tex_coord[1] + w[2] * tri[2].tex_coord[1];
+                let x = ((u * atlas_size[0] as f32) as usize).min(atlas_size[0] - 1);
+                let y = ((v * atlas_size[1] as f32) as usize).min(atlas_size[1] - 1);
+                let texel = atlas[y * atlas_size[0] + x];
+                // same as the discard in the text shader
+                if texel[3] < 0x80 {
+                    return None
+                }
+                // the text is not blended either
+                let c = mix(w, colors);
+                Some([c[0] * texel[0] as f32 / 255.0, c[1] * texel[1] as f32 / 255.0,
+                      c[2] * texel[2] as f32 / 255.0, 1.0])
+            });
+        }
+    }
+
+    /// Draw a frame with the given visuals, replacing the image contents.
+    pub fn render<'a, I>(&mut self, iter: I) where
+        I: Iterator<Item = &'a Drawable>,
+    {
+        while let Ok(view) = self.view_input.try_recv() {
+            self.view = view;
+        }
+        let size = self.image.size;
+        let extents = world::Screen::new(self.extents, 1.0).fit(size).extents;
+        let view = self.view;
+        for p in self.image.pixels.iter_mut() {
+            *p = encode(CLEAR_COLOR);
+        }
+        self.draw_stars(view.scroll, extents);
+        draw::collect_batches(iter, &mut self.batches, &mut self.minimap, &view, extents);
+        let param = draw::get_view_param(&view, extents);
+        self.draw_batches(&param, [0, 0, size[0] as i32, size[1] as i32], false);
+        if view.minimap {
+            let (param, r) = draw::get_minimap_param(&view, extents);
+            let (a, b) = (self.to_pixels([r[0], r[3]]), self.to_pixels([r[2], r[1]]));
+            let clip = [a[0] as i32, a[1] as i32, b[0] as i32, b[1] as i32];
+            self.draw_batches(&param, clip, true);
+        }
+        self.draw_text();
+    }
+}
+
+impl<R: gfx::Resources> pegasus::Painter<R> for Rasterizer {
+    type Visual = Drawable;
+    fn draw<'a, I, C>(&mut self, iter: I, _: &mut gfx::Encoder<R, C>) where
+        I: Iterator<Item = &'a Self::Visual>,
+        C: gfx::CommandBuffer<R>
+    {
+        self.render(iter)
+    }
+}
@@ -0,0 +1,43 @@
+use specs;
+use config;
+use world as w;
+
+// the score system rewards shooting down the asteroids,
+// it runs after the physics in order to see the fresh contacts
+pub struct System {
+    kinds: Vec<config::AsteroidKind>,
+    scored: Vec<specs::Entity>,
+}
+
+impl System {
+    pub fn new(kinds: Vec<config::AsteroidKind>) -> System {
+        System {
+            kinds: kinds,
+            scored: Vec::new(),
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, _: super::Delta) {
+        let (contacts, bullet, aster, mut status) = arg.fetch(|w|
+            (w.read_resource::<w::Contacts>(), w.read::<w::Bullet>(),
+             w.read::<w::Asteroid>(), w.write_resource::<w::Status>())
+        );
+        for contact in contacts.0.iter() {
+            for &(target, shooter) in [(0, 1), (1, 0)].iter() {
+                let e = contact.entities[target];
+                if !contact.destroyed[target] || self.scored.contains(&e) {
+                    continue
+                }
+                if let (Some(a), Some(b)) = (aster.get(e), bullet.get(contact.entities[shooter])) {
+                    if let Some(ps) = status.players.get_mut(b.owner as usize) {
+                        ps.score += self.kinds[a.kind as usize].score;
+                    }
+                    self.scored.push(e);
+                }
+            }
+        }
+        self.scored.clear();
+    }
+}
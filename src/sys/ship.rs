@@ -0,0 +1,110 @@
+use cgmath::{Rad, Point2, Vector2};
+use specs;
+use config;
+use world as w;
+
+
+/// Distance between the spawn points of the players.
+const SPAWN_SPACING: f32 = 2.0;
+const EXHAUST_COLOR: [f32; 4] = [1.0, 0.7, 0.3, 1.0];
+
+// the ship system keeps track of the lives,
+// (re-)spawning the player ships while there are lives left
+pub struct System {
+    drawables: Vec<w::Drawable>,
+    config: config::Ship,
+    blink_period: f32,
+}
+
+impl System {
+    /// Create the system, given a visual for each of the players.
+    /// The ships blink with the given period while invulnerable.
+    pub fn new(drawables: Vec<w::Drawable>, config: config::Ship, blink_period: f32) -> System {
+        System {
+            drawables: drawables,
+            config: config,
+            blink_period: blink_period,
+        }
+    }
+
+    fn spawn(&self, w: &specs::World, player: w::PlayerId, num_players: usize) -> specs::Entity {
+        let offset = player as f32 - 0.5 * (num_players - 1) as f32;
+        w.create_later_build()
+            .with(self.drawables[player as usize % self.drawables.len()].clone())
+            .with(w::Spatial {
+                pos: Point2::new(offset * SPAWN_SPACING, 0.0),
+                orient: Rad{ s: 0.0 },
+                scale: 1.0,
+            })
+            .with(w::Inertial {
+                velocity: Vector2::new(0.0, 0.0),
+                angular_velocity: Rad{ s:0.0 },
+            })
+            .with(w::Control {
+                player: player,
+                thrust_speed: self.config.thrust_speed,
+                // positive turn input goes clockwise
+                turn_speed: -self.config.turn_speed,
+            })
+            .with(w::Collision {
+                radius: self.config.radius,
+                health: self.config.health,
+                damage: self.config.damage,
+            })
+            .with(w::Invulnerable {
+                time_left: self.config.invulnerable_time,
+            })
+            .with(w::Tint::blink(self.blink_period, self.config.invulnerable_time))
+            .with(w::Emitter {
+                active: false,
+                rate: self.config.exhaust_rate,
+                speed: self.config.exhaust_speed,
+                color: EXHAUST_COLOR,
+                pending: 0.0,
+            })
+            .build()
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        use specs::Join;
+        let (mut invulnerable, entities) = arg.fetch(|w| {
+            let mut status = w.write_resource::<w::Status>();
+            let alive: Vec<_> = (&w.read::<w::Control>()).iter()
+                .map(|c| c.player)
+                .collect();
+            let num_players = status.players.len();
+            for (i, ps) in status.players.iter_mut().enumerate() {
+                let player = i as w::PlayerId;
+                ps.phase = match ps.phase {
+                    w::Phase::Spawning(t) if t > time => w::Phase::Spawning(t - time),
+                    w::Phase::Spawning(_) => {
+                        self.spawn(w, player, num_players);
+                        w::Phase::Playing
+                    },
+                    w::Phase::Playing if !alive.contains(&player) => {
+                        ps.lives -= 1;
+                        if ps.lives == 0 {
+                            w::Phase::GameOver
+                        } else {
+                            w::Phase::Spawning(self.config.respawn_time)
+                        }
+                    },
+                    other => other,
+                };
+            }
+            (w.write::<w::Invulnerable>(), w.entities())
+        });
+        let mut expired = Vec::new();
+        for (inv, e) in (&mut invulnerable, &entities).iter() {
+            inv.time_left -= time;
+            if inv.time_left <= 0.0 {
+                expired.push(e);
+            }
+        }
+        for e in expired {
+            invulnerable.remove(e);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use rand::{Rng, SeedableRng, StdRng};
+
+
+/// Parallax, number of stars and color of each layer,
+/// from the farthest to the closest.
+const LAYERS: [(f32, usize, u32); 3] = [
+    (0.1, 200, 0x50505800),
+    (0.25, 100, 0x8888A000),
+    (0.5, 40, 0xD0D0FF00),
+];
+
+/// Stars at the same distance, scrolling together.
+pub struct Layer {
+    /// How far the layer scrolls, relative to the camera.
+    pub parallax: f32,
+    pub color: u32,
+    /// Positions over the screen, from zero to one.
+    pub stars: Vec<[f32; 2]>,
+}
+
+pub fn make_layers(seed: usize) -> Vec<Layer> {
+    // keep the sequence apart from the ones of the other systems
+    let mut rng = StdRng::from_seed(&[seed, 3]);
+    LAYERS.iter().map(|&(parallax, count, color)| Layer {
+        parallax: parallax,
+        color: color,
+        stars: (0 .. count).map(|_| [rng.gen(), rng.gen()]).collect(),
+    }).collect()
+}
+
+/// Shift of the layer after scrolling by the given distance in world units,
+/// in screen sizes.
+pub fn get_offset(parallax: f32, scroll: [f32; 2], extents: [f32; 2]) -> [f32; 2] {
+    [0.5 * scroll[0] * parallax / extents[0], 0.5 * scroll[1] * parallax / extents[1]]
+}
+
+/// Where a star is seen, in screen coordinates from -1 to 1.
+/// Same as the star shader of the painter.
+pub fn project(star: [f32; 2], offset: [f32; 2]) -> [f32; 2] {
+    let fract = |v: f32| v - v.floor();
+    [2.0 * fract(star[0] - offset[0]) - 1.0, 2.0 * fract(star[1] - offset[1]) - 1.0]
+}
@@ -0,0 +1,85 @@
+use specs;
+use config;
+use world as w;
+
+
+// the tint system animates the colors, flashing the damaged objects
+// and leaving the fading remains of the destroyed ones,
+// it runs after the physics in order to see the fresh contacts
+pub struct System {
+    config: config::Tint,
+}
+
+impl System {
+    pub fn new(config: config::Tint) -> System {
+        System {
+            config: config,
+        }
+    }
+
+    fn spawn_remains(&self, w: &specs::World) {
+        let remains: Vec<_> = {
+            let (contacts, bullet, draw, space, inertia) = (w.read_resource::<w::Contacts>(),
+                w.read::<w::Bullet>(), w.read::<w::Drawable>(), w.read::<w::Spatial>(),
+                w.read::<w::Inertial>());
+            let mut remains = Vec::new();
+            for contact in contacts.0.iter() {
+                for (&e, &destroyed) in contact.entities.iter().zip(contact.destroyed.iter()) {
+                    // bullets are gone in a blink anyway
+                    if !destroyed || bullet.get(e).is_some() ||
+                        remains.iter().any(|&(e2, _, _, _)| e2 == e) {
+                        continue
+                    }
+                    if let (Some(d), Some(s), Some(i)) = (draw.get(e), space.get(e), inertia.get(e)) {
+                        remains.push((e, d.clone(), s.clone(), i.clone()));
+                    }
+                }
+            }
+            remains
+        };
+        for (_, d, s, i) in remains {
+            w.create_later_build()
+                .with(d)
+                .with(s)
+                .with(i)
+                .with(w::Tint::fade_out(self.config.fade_time))
+                .build();
+        }
+    }
+}
+
+impl specs::System<super::Delta> for System {
+    fn run(&mut self, arg: specs::RunArg, time: super::Delta) {
+        use specs::Join;
+        let (contacts, mut tint, mut draw, entities) = arg.fetch(|w| {
+            if self.config.fade_time > 0.0 {
+                self.spawn_remains(w);
+            }
+            (w.read_resource::<w::Contacts>(), w.write::<w::Tint>(), w.write::<w::Drawable>(),
+             w.entities())
+        });
+        for contact in contacts.0.iter() {
+            for i in 0 .. 2 {
+                let e = contact.entities[i];
+                if contact.damage[i] > 0 && !contact.destroyed[i] && draw.get(e).is_some() {
+                    tint.insert(e, w::Tint::flash(self.config.flash_time));
+                }
+            }
+        }
+        let mut finished = Vec::new();
+        for (t, d, e) in (&mut tint, &mut draw, &entities).iter() {
+            t.time += time;
+            d.set_tint(t.get_color());
+            if t.is_done() {
+                finished.push((e, t.vanish));
+            }
+        }
+        for (e, vanish) in finished {
+            if vanish {
+                arg.delete(e);
+            } else {
+                tint.remove(e);
+            }
+        }
+    }
+}
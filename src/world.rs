@@ -1,8 +1,13 @@
 use cgmath::{Rad, Basis2, Rotation, Rotation2, Point2, Vector2};
 use specs;
+pub use sys::debug::Shape as DebugShape;
 pub use sys::draw::Drawable;
 
 
+/// Index of a local player, starting from zero.
+pub type PlayerId = u8;
+
+
 /// --- Components ---
 
 #[derive(Clone)]
@@ -34,6 +39,7 @@ impl specs::Component for Inertial {
 }
 
 pub struct Control {
+    pub player: PlayerId,
     pub thrust_speed: f32,
     pub turn_speed: f32,
 }
@@ -44,6 +50,7 @@ impl specs::Component for Control {
 
 pub struct Bullet {
     pub life_time: Option<f32>,
+    pub owner: PlayerId,
 }
 
 impl specs::Component for Bullet {
@@ -68,3 +75,247 @@ pub struct Collision {
 impl specs::Component for Collision {
     type Storage = specs::VecStorage<Collision>;
 }
+
+/// The entity can't be damaged while the time is not up.
+pub struct Invulnerable {
+    pub time_left: f32,
+}
+
+impl specs::Component for Invulnerable {
+    type Storage = specs::HashMapStorage<Invulnerable>;
+}
+
+/// Source of particles, flying backwards from the entity while active.
+pub struct Emitter {
+    pub active: bool,
+    /// Particles per second.
+    pub rate: f32,
+    pub speed: f32,
+    pub color: [f32; 4],
+    /// Fraction of a particle accumulated since the last one.
+    pub pending: f32,
+}
+
+impl specs::Component for Emitter {
+    type Storage = specs::HashMapStorage<Emitter>;
+}
+
+/// Point of view, shown at the center of the screen.
+pub struct Camera {
+    pub pos: Point2<f32>,
+    pub rotation: Rad<f32>,
+    /// Magnification, with one showing exactly the screen extents.
+    pub zoom: f32,
+    /// The player whose ship is followed.
+    pub target: Option<PlayerId>,
+}
+
+impl specs::Component for Camera {
+    type Storage = specs::HashMapStorage<Camera>;
+}
+
+/// Short-living visual-only entity, fading out with time.
+pub struct Particle {
+    pub life_time: f32,
+    pub time_left: f32,
+    pub color: [f32; 4],
+}
+
+impl specs::Component for Particle {
+    type Storage = specs::VecStorage<Particle>;
+}
+
+/// Timed change of the visual colors, going from one tint to another.
+#[derive(Clone, Debug)]
+pub struct Tint {
+    pub from: [f32; 4],
+    pub to: [f32; 4],
+    /// Switch between the tints with this period, instead of blending them.
+    pub blink: Option<f32>,
+    pub duration: f32,
+    pub time: f32,
+    /// Delete the entity when the time is up.
+    pub vanish: bool,
+}
+
+impl Tint {
+    /// Brighten the colors, going back to normal.
+    pub fn flash(duration: f32) -> Tint {
+        Tint {
+            from: [2.0, 2.0, 2.0, 1.0],
+            to: [1.0; 4],
+            blink: None,
+            duration: duration,
+            time: 0.0,
+            vanish: false,
+        }
+    }
+
+    /// Go half-transparent and back, again and again.
+    pub fn blink(period: f32, duration: f32) -> Tint {
+        Tint {
+            from: [1.0, 1.0, 1.0, 0.3],
+            to: [1.0; 4],
+            blink: Some(period),
+            duration: duration,
+            time: 0.0,
+            vanish: false,
+        }
+    }
+
+    /// Become transparent and disappear.
+    pub fn fade_out(duration: f32) -> Tint {
+        Tint {
+            from: [1.0; 4],
+            to: [1.0, 1.0, 1.0, 0.0],
+            blink: None,
+            duration: duration,
+            time: 0.0,
+            vanish: true,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.time >= self.duration
+    }
+
+    pub fn get_color(&self) -> [f32; 4] {
+        let t = match self.blink {
+            _ if self.is_done() => 1.0,
+            Some(period) => if (self.time / period).fract() < 0.5 { 0.0 } else { 1.0 },
+            None => self.time / self.duration,
+        };
+        let mut color = [0.0; 4];
+        for i in 0 .. 4 {
+            color[i] = self.from[i] + (self.to[i] - self.from[i]) * t;
+        }
+        color
+    }
+}
+
+impl specs::Component for Tint {
+    type Storage = specs::HashMapStorage<Tint>;
+}
+
+/// Flipping through the frames of a sprite sheet.
+#[derive(Clone)]
+pub struct Animation {
+    pub num_frames: u32,
+    /// Frames per second.
+    pub rate: f32,
+    pub time: f32,
+    /// Start over after the last frame, instead of staying on it.
+    pub repeat: bool,
+}
+
+impl Animation {
+    pub fn get_frame(&self) -> u32 {
+        let frame = (self.time * self.rate) as u32;
+        if self.repeat {
+            frame % self.num_frames.max(1)
+        } else {
+            frame.min(self.num_frames.saturating_sub(1))
+        }
+    }
+}
+
+impl specs::Component for Animation {
+    type Storage = specs::HashMapStorage<Animation>;
+}
+
+
+/// --- Resources ---
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    /// Waiting for the ship to (re-)appear.
+    Spawning(f32),
+    Playing,
+    GameOver,
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    pub score: u32,
+    pub lives: u8,
+    pub phase: Phase,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub players: Vec<PlayerStatus>,
+    /// Counted from one, advancing as the large asteroids keep coming.
+    pub wave: u32,
+}
+
+impl Status {
+    pub fn is_game_over(&self) -> bool {
+        self.players.iter().all(|p| p.phase == Phase::GameOver)
+    }
+}
+
+/// The visible part of the world, centered at the origin.
+#[derive(Clone, Copy, Debug)]
+pub struct Screen {
+    /// Half-size of the visible area, in world units.
+    pub extents: [f32; 2],
+    /// Half-size of the level, where everything is spawned and wrapped.
+    pub level: [f32; 2],
+}
+
+impl Screen {
+    /// Create a screen with the level of the given size relative to it.
+    pub fn new(extents: [f32; 2], level_scale: f32) -> Screen {
+        Screen {
+            extents: extents,
+            level: [extents[0] * level_scale, extents[1] * level_scale],
+        }
+    }
+
+    /// Match the shape of a window, keeping the height of the playfield.
+    /// The level stays the same, not to change the world in the middle of a game.
+    pub fn fit(&self, size: [u32; 2]) -> Screen {
+        let aspect = size[0] as f32 / size[1].max(1) as f32;
+        Screen {
+            extents: [self.extents[1] * aspect, self.extents[1]],
+            level: self.level,
+        }
+    }
+
+    /// Match the shape of a window with both the screen and the level,
+    /// which is only done before the game starts.
+    pub fn fit_level(&self, size: [u32; 2]) -> Screen {
+        Screen::new(self.fit(size).extents, self.level[1] / self.extents[1])
+    }
+
+    /// Whether some of the level is out of the screen.
+    pub fn is_level_bigger(&self) -> bool {
+        self.level[0] > self.extents[0] || self.level[1] > self.extents[1]
+    }
+}
+
+/// Switches of the visual effects, not affecting the simulation.
+#[derive(Clone, Copy, Debug)]
+pub struct Effects {
+    /// Show the vector display look.
+    pub glow: bool,
+    /// Let the systems draw their internals.
+    pub debug: bool,
+}
+
+/// Shapes pushed by the systems to be drawn on top of the game
+/// while debugging, taken away by the pre-draw system every frame.
+pub struct DebugShapes(pub Vec<DebugShape>);
+
+/// Two entities hitting each other.
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub entities: [specs::Entity; 2],
+    /// Damage dealt to each of the entities.
+    pub damage: [u16; 2],
+    pub point: Point2<f32>,
+    pub destroyed: [bool; 2],
+}
+
+/// All the contacts found by the last physics step.
+pub struct Contacts(pub Vec<Contact>);